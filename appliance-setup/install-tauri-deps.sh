@@ -61,7 +61,9 @@ sudo apt install -y \
   libgtk-3-dev \
   libappindicator3-dev \
   libwebkit2gtk-4.0-dev \
-  libudev-dev
+  libudev-dev \
+  libpcsclite-dev \
+  pcscd
 
 # Install GStreamer and media codec libraries for AppImage compatibility
 echo "🎵 Installing GStreamer and media codec libraries..."
@@ -0,0 +1,69 @@
+use crate::config::config_manager::Config;
+use crate::error::GuestbookError;
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri_plugin_http::reqwest;
+
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Idle pooled connections are dropped after this long.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// HTTP client shared by all API calls, held in Tauri state alongside
+/// `ConfigManager` so connections to the server are reused.
+pub struct ApiClient {
+    client: RwLock<reqwest::Client>,
+}
+
+impl ApiClient {
+    pub fn new(config: &Config) -> Result<Self, GuestbookError> {
+        Ok(Self {
+            client: RwLock::new(Self::build(config)?),
+        })
+    }
+
+    /// Cheap handle to the pooled client (`reqwest::Client` is reference counted).
+    pub fn http(&self) -> reqwest::Client {
+        self.client.read().unwrap().clone()
+    }
+
+    /// Rebuild the client after the network settings changed. Requests already
+    /// in flight keep using the old client.
+    pub fn reconfigure(&self, config: &Config) -> Result<(), GuestbookError> {
+        let client = Self::build(config)?;
+        *self.client.write().unwrap() = client;
+        Ok(())
+    }
+
+    fn build(config: &Config) -> Result<reqwest::Client, GuestbookError> {
+        let user_agent = format!(
+            "guestbook-kiosk/{} (device {})",
+            env!("CARGO_PKG_VERSION"),
+            config.device_id.as_deref().unwrap_or("unknown")
+        );
+        let mut builder = reqwest::Client::builder()
+            .user_agent(user_agent)
+            .connect_timeout(Duration::from_secs(
+                config
+                    .connect_timeout_secs
+                    .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+            ))
+            .timeout(Duration::from_secs(
+                config
+                    .request_timeout_secs
+                    .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS),
+            ))
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .tcp_keepalive(TCP_KEEPALIVE);
+        if let Some(proxy_url) = config.http_proxy.as_deref().filter(|p| !p.is_empty()) {
+            let proxy = reqwest::Proxy::all(proxy_url).map_err(|e| {
+                GuestbookError::Config(format!("Invalid HTTP proxy {:?}: {}", proxy_url, e))
+            })?;
+            builder = builder.proxy(proxy);
+        }
+        builder
+            .build()
+            .map_err(|e| GuestbookError::Config(format!("Failed to build HTTP client: {}", e)))
+    }
+}
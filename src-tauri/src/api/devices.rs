@@ -1,73 +1,264 @@
+use crate::api::client::ApiClient;
+use crate::api::telemetry::Telemetry;
 use crate::config::config_manager::{get_full_config, ConfigManager};
+use crate::error::GuestbookError;
 use chrono::Utc;
+use serde::Deserialize;
 use serde_json::json;
-use tauri_plugin_http::reqwest; // Add this import for the `json!` macro
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Minimum time between automatic re-registrations, so a server that rejects
+/// every token can't make the kiosk register in a loop.
+const REREGISTRATION_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+lazy_static::lazy_static! {
+    /// Time of the last automatic re-registration. Held across the request so
+    /// concurrent callers (heartbeat, outbox) don't register twice.
+    static ref LAST_REREGISTRATION: tokio::sync::Mutex<Option<Instant>> =
+        tokio::sync::Mutex::new(None);
+}
 
 pub async fn register_device(
     config_manager: tauri::State<'_, ConfigManager>,
-) -> Result<(), String> {
+    api_client: &ApiClient,
+) -> Result<(), GuestbookError> {
     let config = get_full_config(config_manager.clone());
-    let register_url = format!("{}/devices/register", config.server_url.clone().unwrap());
-    let client = reqwest::Client::new();
-    let response = client
+    let register_url = format!("{}/devices/register", config.require_server_url()?);
+    let response = api_client
+        .http()
         .post(register_url)
         .header("Content-Type", "application/json")
-        .body(
-            serde_json::to_string(&json!({
-                "name": config.device_friendly_name,
-                "location": config.device_location,
-                "id": config.device_id,
-            }))
-            .unwrap(),
-        )
+        .body(serde_json::to_string(&json!({
+            "name": config.device_friendly_name,
+            "location": config.device_location,
+            "id": config.device_id,
+        }))?)
         .send()
-        .await;
-    if response.is_err() {
-        return Err(response.err().unwrap().to_string());
+        .await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(GuestbookError::from_status(status.as_u16(), body));
     }
-    let body = response.unwrap().text().await.unwrap();
-    let json: serde_json::Value =
-        serde_json::from_str(&body).map_err(|e| format!("Failed to parse response JSON: {}", e))?;
+    let json: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|e| GuestbookError::Parse(format!("Failed to parse response JSON: {}", e)))?;
     let token = json
         .get("token")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| "Token not found in response".to_string())?
+        .ok_or_else(|| GuestbookError::Parse("Token not found in response".to_string()))?
         .to_owned();
     config_manager.set_server_token(token.clone());
     Ok(())
 }
 
-pub async fn send_heartbeat(config_manager: tauri::State<'_, ConfigManager>) -> Result<(), String> {
+/// Body of a heartbeat response. Commands are kept as raw JSON so one the
+/// client doesn't understand can still be acknowledged by id.
+#[derive(Deserialize, Default, Debug)]
+pub struct HeartbeatResponse {
+    #[serde(default)]
+    pub commands: Vec<serde_json::Value>,
+}
+
+pub async fn send_heartbeat(
+    config_manager: tauri::State<'_, ConfigManager>,
+    api_client: &ApiClient,
+    telemetry: &Telemetry,
+) -> Result<HeartbeatResponse, GuestbookError> {
     let config = get_full_config(config_manager.clone());
-    let device_id = config.device_id.clone().unwrap();
     let heartbeat_url = format!(
         "{}/devices/heartbeat/{}",
-        config.server_url.clone().unwrap(),
-        device_id
+        config.require_server_url()?,
+        config.require_device_id()?
     );
     let current_time = Utc::now().timestamp_millis();
-    println!("Heartbeat Sending at {}", current_time);
-    let client = reqwest::Client::new();
-    let response = client
-        .get(heartbeat_url)
+    log::debug!("Heartbeat sending at {}", current_time);
+    let resp = api_client
+        .http()
+        .post(heartbeat_url)
         .header("Content-Type", "application/json")
         .header(
             "Authorization",
-            format!("Bearer {}", config.server_token.clone().unwrap()),
+            format!("Bearer {}", config.require_server_token()?),
         )
+        .body(serde_json::to_string(telemetry)?)
         .send()
-        .await;
-    if response.is_err() {
-        return Err(response.err().unwrap().to_string());
+        .await?;
+    if !resp.status().is_success() {
+        let status = resp.status().as_u16();
+        let body = resp
+            .text()
+            .await
+            .unwrap_or_else(|_| "<no body>".to_string());
+        return Err(GuestbookError::from_status(status, body));
+    }
+    let body = resp.text().await?;
+    if body.trim().is_empty() {
+        return Ok(HeartbeatResponse::default());
     }
-    let resp = response.unwrap();
+    // Older servers answer with plain text; treat that as "no commands"
+    Ok(serde_json::from_str(&body).unwrap_or_else(|e| {
+        log::debug!("Heartbeat response is not a command list: {}", e);
+        HeartbeatResponse::default()
+    }))
+}
+
+/// `/devices/commands/{id}/ack` under the server URL. The id comes from the
+/// server, so it is escaped as a single path segment.
+fn command_ack_url(server_url: &str, command_id: &str) -> Result<url::Url, GuestbookError> {
+    let invalid = || GuestbookError::Config(format!("Invalid server URL {:?}", server_url));
+    let mut url = url::Url::parse(server_url).map_err(|_| invalid())?;
+    url.path_segments_mut()
+        .map_err(|_| invalid())?
+        .pop_if_empty()
+        .extend(["devices", "commands", command_id, "ack"]);
+    Ok(url)
+}
+
+/// Report the outcome of a remote command back to the server.
+pub async fn acknowledge_command(
+    config_manager: tauri::State<'_, ConfigManager>,
+    api_client: &ApiClient,
+    command_id: &str,
+    status: &str,
+    message: &str,
+) -> Result<(), GuestbookError> {
+    let config = get_full_config(config_manager);
+    let ack_url = command_ack_url(config.require_server_url()?, command_id)?;
+    let resp = api_client
+        .http()
+        .post(ack_url)
+        .header("Content-Type", "application/json")
+        .header(
+            "Authorization",
+            format!("Bearer {}", config.require_server_token()?),
+        )
+        .body(serde_json::to_string(&json!({
+            "status": status,
+            "message": message,
+        }))?)
+        .send()
+        .await?;
     if !resp.status().is_success() {
-        let status = resp.status();
+        let status = resp.status().as_u16();
         let body = resp
             .text()
             .await
             .unwrap_or_else(|_| "<no body>".to_string());
-        return Err(format!("Heartbeat failed: status {}: {}", status, body));
+        return Err(GuestbookError::from_status(status, body));
     }
     Ok(())
 }
+
+/// Upload the tail of a log file as plain text.
+pub async fn upload_log(
+    config_manager: tauri::State<'_, ConfigManager>,
+    api_client: &ApiClient,
+    file_name: &str,
+    contents: Vec<u8>,
+) -> Result<(), GuestbookError> {
+    let config = get_full_config(config_manager);
+    let upload_url = format!(
+        "{}/devices/{}/logs",
+        config.require_server_url()?,
+        config.require_device_id()?
+    );
+    let resp = api_client
+        .http()
+        .post(upload_url)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .header("X-Log-File", file_name)
+        .header(
+            "Authorization",
+            format!("Bearer {}", config.require_server_token()?),
+        )
+        .body(contents)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        let status = resp.status().as_u16();
+        let body = resp
+            .text()
+            .await
+            .unwrap_or_else(|_| "<no body>".to_string());
+        return Err(GuestbookError::from_status(status, body));
+    }
+    Ok(())
+}
+
+/// Register again after the server rejected `rejected_token`, unless another
+/// task already replaced it or the cooldown hasn't expired yet.
+async fn reregister_device(
+    config_manager: tauri::State<'_, ConfigManager>,
+    api_client: &ApiClient,
+    rejected_token: Option<&str>,
+    rejection: GuestbookError,
+) -> Result<(), GuestbookError> {
+    let mut last = LAST_REREGISTRATION.lock().await;
+    let current_token = get_full_config(config_manager.clone()).server_token;
+    if current_token.as_deref() != rejected_token {
+        log::info!("Device token was already renewed, replaying request");
+        return Ok(());
+    }
+    if let Some(at) = *last {
+        if at.elapsed() < REREGISTRATION_COOLDOWN {
+            log::warn!(
+                "Device token rejected again within {}s of re-registering, not retrying",
+                REREGISTRATION_COOLDOWN.as_secs()
+            );
+            return Err(rejection);
+        }
+    }
+    *last = Some(Instant::now());
+    log::warn!("Device token rejected, re-registering device");
+    register_device(config_manager, api_client).await?;
+    log::info!("Device re-registered with a new token");
+    Ok(())
+}
+
+/// Run an authenticated request; if the server rejects the device token,
+/// re-register with the stored device details and replay it once.
+pub async fn with_reregistration<'r, T, F, Fut>(
+    config_manager: tauri::State<'r, ConfigManager>,
+    api_client: &ApiClient,
+    request: F,
+) -> Result<T, GuestbookError>
+where
+    F: Fn(tauri::State<'r, ConfigManager>) -> Fut,
+    Fut: Future<Output = Result<T, GuestbookError>>,
+{
+    let token = get_full_config(config_manager.clone()).server_token;
+    match request(config_manager.clone()).await {
+        Err(rejection @ GuestbookError::Unauthorized { .. }) => {
+            reregister_device(
+                config_manager.clone(),
+                api_client,
+                token.as_deref(),
+                rejection,
+            )
+            .await?;
+            request(config_manager).await
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_ids_are_escaped_in_the_ack_url() {
+        let url = command_ack_url("https://guestbook.example.edu/api/v1/", "c1").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://guestbook.example.edu/api/v1/devices/commands/c1/ack"
+        );
+        let url = command_ack_url("https://guestbook.example.edu/api/v1", "../../x?y#z").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://guestbook.example.edu/api/v1/devices/commands/..%2F..%2Fx%3Fy%23z/ack"
+        );
+        assert!(command_ack_url("not a url", "c1").is_err());
+    }
+}
@@ -1,42 +1,125 @@
+use crate::api::client::ApiClient;
 use crate::config::config_manager::{get_full_config, ConfigManager};
-use chrono::Utc;
+use crate::devices::magnesafe::EncryptedSwipe;
+use crate::devices::visitor_pass::VisitorPass;
+use crate::error::GuestbookError;
 use serde::{Deserialize, Serialize};
 use serde_json::json; // Add this import for the `json!` macro
-use tauri_plugin_http::reqwest;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CardData {
     pub onecard: String,
     pub name: String,
+    /// Encrypted tracks and KSN from a MagneSafe reader, sent to the server untouched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<EncryptedSwipe>,
 }
+
+/// Something presented at the kiosk that is recorded as an entry.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    Card(CardData),
+    VisitorPass(VisitorPass),
+}
+
+/// How repeat presentations of the same card are recorded.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryMode {
+    /// Every presentation is an entry.
+    #[default]
+    Entry,
+    /// Presentations alternate between check-in and check-out, starting
+    /// with check-in each day.
+    CheckInOut,
+}
+
+/// Whether a check-in/check-out entry is someone arriving or leaving.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "in" => Some(Direction::In),
+            "out" => Some(Direction::Out),
+            _ => None,
+        }
+    }
+}
+
+impl Entry {
+    /// The OneCard number or pass ID, for logs.
+    pub fn id(&self) -> &str {
+        match self {
+            Entry::Card(card) => &card.onecard,
+            Entry::VisitorPass(pass) => &pass.pass_id,
+        }
+    }
+}
+
+/// POST a single entry to the server. `entry_time` is the time the card was
+/// swiped/scanned, which may be well before the submission when replaying the outbox.
+/// `direction` is only set in check-in/check-out mode.
 pub async fn submit_entry(
     config_manager: tauri::State<'_, ConfigManager>,
-    card_data: CardData,
-) -> Result<(), String> {
+    api_client: &ApiClient,
+    entry: Entry,
+    entry_time: &str,
+    direction: Option<Direction>,
+) -> Result<(), GuestbookError> {
     let config = get_full_config(config_manager.clone());
-    let submit_url = format!("{}/entries/submit", config.server_url.clone().unwrap());
-    let client = reqwest::Client::new();
-    let response = client
+    let submit_url = format!("{}/entries/submit", config.require_server_url()?);
+    let mut body = match &entry {
+        Entry::Card(card_data) => json!({
+            "device_id": config.device_id,
+            "guest": card_data,
+            "timestamp": entry_time,
+        }),
+        Entry::VisitorPass(pass) => json!({
+            "device_id": config.device_id,
+            "visitor_pass": pass,
+            "timestamp": entry_time,
+        }),
+    };
+    if let Some(direction) = direction {
+        body["direction"] = json!(direction);
+    }
+    let response = api_client
+        .http()
         .post(submit_url)
         .header("Content-Type", "application/json")
         .header(
             "Authorization",
-            format!("Bearer {}", config.server_token.clone().unwrap()),
-        )
-        .body(
-            serde_json::to_string(&json!({
-                "device_id": config.device_id,
-                "guest": card_data.clone(),
-                "timestamp": Utc::now().to_rfc3339(),
-            }))
-            .unwrap(),
+            format!("Bearer {}", config.require_server_token()?),
         )
+        .body(serde_json::to_string(&body)?)
         .send()
-        .await;
-    if response.is_err() {
-        return Err(response.err().unwrap().to_string());
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let body = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "<no body>".to_string());
+        return Err(GuestbookError::from_status(status, body));
     }
-    let _body = response.unwrap().text().await.unwrap();
-    println!("{}{}", card_data.onecard, Utc::now().to_rfc3339());
+    log::debug!(
+        "Entry for {} recorded at {} submitted",
+        entry.id(),
+        entry_time
+    );
     Ok(())
 }
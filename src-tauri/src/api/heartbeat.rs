@@ -0,0 +1,195 @@
+use crate::api::client::ApiClient;
+use crate::api::devices::{send_heartbeat, with_reregistration};
+use crate::api::remote::run_commands;
+use crate::api::telemetry::collect_telemetry;
+use crate::config::config_manager::{get_full_config, ConfigManager};
+use crate::error::GuestbookError;
+use chrono::Utc;
+use log::{info, warn};
+use rand::Rng;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 10 * 60;
+/// Longest interval used, so a mistyped config can't silence a kiosk for good.
+const MAX_HEARTBEAT_INTERVAL_SECS: u64 = 24 * 60 * 60;
+/// Delay before the first heartbeat after startup.
+const INITIAL_DELAY: Duration = Duration::from_secs(15);
+/// Consecutive missed beats before the kiosk is reported as offline.
+const MISSED_BEATS_OFFLINE: u32 = 2;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ConnectivityStatus {
+    pub online: bool,
+    pub missed_beats: u32,
+    pub last_success: Option<String>,
+    pub last_error: Option<String>,
+}
+
+impl Default for ConnectivityStatus {
+    // Assume online until enough beats are missed, matching the UI's initial state
+    fn default() -> Self {
+        Self {
+            online: true,
+            missed_beats: 0,
+            last_success: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Tracks heartbeat results; emits `connectivity-changed` when the kiosk
+/// goes offline or comes back.
+pub struct HeartbeatMonitor {
+    started: Instant,
+    status: Mutex<ConnectivityStatus>,
+}
+
+impl Default for HeartbeatMonitor {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            status: Mutex::new(ConnectivityStatus::default()),
+        }
+    }
+}
+
+impl HeartbeatMonitor {
+    /// Time since the app started (the monitor is created in `main`).
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn status(&self) -> ConnectivityStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Record a heartbeat result and return the new status if `online` flipped.
+    fn record(&self, result: &Result<(), GuestbookError>) -> Option<ConnectivityStatus> {
+        let mut status = self.status.lock().unwrap();
+        let was_online = status.online;
+        match result {
+            Ok(()) => {
+                status.online = true;
+                status.missed_beats = 0;
+                status.last_success = Some(Utc::now().to_rfc3339());
+                status.last_error = None;
+            }
+            Err(e) => {
+                status.missed_beats = status.missed_beats.saturating_add(1);
+                status.last_error = Some(e.to_string());
+                if status.missed_beats >= MISSED_BEATS_OFFLINE {
+                    status.online = false;
+                }
+            }
+        }
+        (status.online != was_online).then(|| status.clone())
+    }
+}
+
+/// Send one heartbeat, update the monitor and notify the UI on changes, then
+/// run any commands the server sent back.
+pub async fn beat(app: &AppHandle) -> Result<(), GuestbookError> {
+    let config_manager = app.state::<ConfigManager>();
+    let api_client = app.state::<ApiClient>();
+    let telemetry = collect_telemetry(app).await;
+    let result = with_reregistration(config_manager, &api_client, |config_manager| {
+        send_heartbeat(config_manager, &api_client, &telemetry)
+    })
+    .await;
+    let outcome = result.as_ref().map(|_| ()).map_err(Clone::clone);
+    match &outcome {
+        Ok(()) => info!("Heartbeat sent successfully"),
+        Err(e) => warn!("Heartbeat failed: {}", e),
+    }
+    if let Some(status) = app.state::<HeartbeatMonitor>().record(&outcome) {
+        info!(
+            "Connectivity changed: {} ({} missed beats)",
+            if status.online { "online" } else { "offline" },
+            status.missed_beats
+        );
+        app.emit("connectivity-changed", status).ok();
+    }
+    if let Ok(response) = result {
+        run_commands(app, response.commands).await;
+    }
+    outcome
+}
+
+/// Interval from config with ±10% jitter so kiosks don't beat in lockstep.
+fn next_interval(app: &AppHandle) -> Duration {
+    jittered_interval(
+        get_full_config(app.state::<ConfigManager>())
+            .heartbeat_interval_secs
+            .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECS),
+    )
+}
+
+/// `secs`, clamped to between a second and `MAX_HEARTBEAT_INTERVAL_SECS`,
+/// with ±10% jitter.
+fn jittered_interval(secs: u64) -> Duration {
+    let secs = secs.clamp(1, MAX_HEARTBEAT_INTERVAL_SECS);
+    let jitter = secs / 10;
+    let spread = rand::rng().random_range(0..=jitter.saturating_mul(2));
+    Duration::from_secs(secs.saturating_sub(jitter).saturating_add(spread))
+}
+
+/// Spawn the heartbeat task; it runs independently of the webview.
+pub fn start_heartbeat_scheduler(app: AppHandle) {
+    info!("Starting heartbeat scheduler");
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(INITIAL_DELAY).await;
+        loop {
+            let registered = get_full_config(app.state::<ConfigManager>())
+                .server_token
+                .is_some();
+            if registered {
+                beat(&app).await.ok();
+            }
+            tokio::time::sleep(next_interval(&app)).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_connectivity_status(monitor: tauri::State<'_, HeartbeatMonitor>) -> ConnectivityStatus {
+    monitor.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goes_offline_after_missed_beats_and_back_online() {
+        let monitor = HeartbeatMonitor::default();
+        let missed = Err(GuestbookError::Network("timed out".into()));
+
+        assert!(monitor.record(&missed).is_none());
+        let offline = monitor.record(&missed).expect("second miss goes offline");
+        assert!(!offline.online);
+        assert_eq!(offline.missed_beats, MISSED_BEATS_OFFLINE);
+        assert!(monitor.record(&missed).is_none());
+
+        let online = monitor.record(&Ok(())).expect("success comes back online");
+        assert!(online.online);
+        assert_eq!(online.missed_beats, 0);
+        assert!(monitor.record(&Ok(())).is_none());
+    }
+
+    #[test]
+    fn interval_stays_in_range_for_any_config() {
+        for (secs, min, max) in [
+            (0, 1, 1),
+            (600, 540, 660),
+            (u64::MAX, 21 * 60 * 60 + 36 * 60, 26 * 60 * 60 + 24 * 60),
+        ] {
+            for _ in 0..100 {
+                let interval = jittered_interval(secs).as_secs();
+                assert!((min..=max).contains(&interval), "{} -> {}", secs, interval);
+            }
+        }
+    }
+}
@@ -1,2 +1,8 @@
+pub mod client;
 pub mod devices;
-pub mod entries;
\ No newline at end of file
+pub mod entries;
+pub mod heartbeat;
+pub mod outbox;
+pub mod remote;
+pub mod retry;
+pub mod telemetry;
@@ -0,0 +1,551 @@
+use crate::api::client::ApiClient;
+use crate::api::devices::with_reregistration;
+use crate::api::entries::{submit_entry, CardData, Direction, Entry, EntryMode};
+use crate::api::retry::{classify_status, Disposition, RetryPolicy};
+use crate::config::config_manager::{get_full_config, ConfigManager};
+use crate::db::{Db, GuestEntry, LastEntry, CARD_KIND, VISITOR_PASS_KIND};
+use crate::error::GuestbookError;
+use chrono::{DateTime, Local, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+
+/// How long the worker sleeps between drain attempts when nothing wakes it up.
+const IDLE_INTERVAL: Duration = Duration::from_secs(30);
+/// Entries fetched from the database per drain pass.
+const DRAIN_BATCH_SIZE: u32 = 50;
+/// Delivered entries are kept locally for this many days before being pruned.
+const SENT_RETENTION_DAYS: i64 = 30;
+/// Seconds a card or pass is ignored after an entry when
+/// `duplicate_window_secs` is unset.
+const DEFAULT_DUPLICATE_WINDOW_SECS: u64 = 10;
+
+/// Backoff state shared between the worker and `get_outbox_status`.
+#[derive(Default)]
+struct Schedule {
+    consecutive_failures: u32,
+    next_retry_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct OutboxStatus {
+    pub pending: u32,
+    pub dead: u32,
+    pub next_retry_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// What `enqueue` did with an entry, returned by the submit commands so the
+/// frontend only reports success for entries that were queued.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Enqueued {
+    /// Queued for submission, with its direction in check-in/check-out mode.
+    Queued { direction: Option<Direction> },
+    /// The same card or pass was entered at `last_entry_time`, within the
+    /// duplicate window, so nothing was queued.
+    Duplicate { last_entry_time: String },
+}
+
+/// Payload of the `duplicate-entry` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct DuplicateEntry {
+    /// OneCard number or pass ID.
+    pub id: String,
+    pub last_entry_time: String,
+    pub window_secs: u64,
+}
+
+/// Decide what an entry made at `now` is, given the last one for the same
+/// card or pass. A check-in/check-out day starts with check-in, so someone
+/// who never checked out yesterday isn't checked out today.
+fn admit(
+    last: Option<&LastEntry>,
+    now: DateTime<Utc>,
+    window: Duration,
+    mode: EntryMode,
+) -> Enqueued {
+    let last_time = last.and_then(|last| {
+        DateTime::parse_from_rfc3339(&last.entry_time)
+            .map(|time| time.with_timezone(&Utc))
+            .ok()
+    });
+    if let (Some(last), Some(last_time)) = (last, last_time) {
+        // A clock set back makes the last entry look like it's in the future; ignore it then
+        let duplicate = (now - last_time)
+            .to_std()
+            .is_ok_and(|elapsed| elapsed < window);
+        if duplicate {
+            return Enqueued::Duplicate {
+                last_entry_time: last.entry_time.clone(),
+            };
+        }
+    }
+    if mode != EntryMode::CheckInOut {
+        return Enqueued::Queued { direction: None };
+    }
+    let checked_in_today = last.zip(last_time).is_some_and(|(last, last_time)| {
+        last.direction.as_deref().and_then(Direction::parse) == Some(Direction::In)
+            && last_time.with_timezone(&Local).date_naive()
+                == now.with_timezone(&Local).date_naive()
+    });
+    let direction = if checked_in_today {
+        Direction::Out
+    } else {
+        Direction::In
+    };
+    Enqueued::Queued {
+        direction: Some(direction),
+    }
+}
+
+/// Rebuild the entry to submit from its database row.
+fn stored_entry(row: &GuestEntry) -> Result<Entry, String> {
+    if row.kind == VISITOR_PASS_KIND {
+        let json = row.pass.as_deref().ok_or("visitor pass data is missing")?;
+        return serde_json::from_str(json)
+            .map(Entry::VisitorPass)
+            .map_err(|e| format!("visitor pass data is unreadable: {}", e));
+    }
+    Ok(Entry::Card(CardData {
+        onecard: row.onecard.clone(),
+        name: row.name.clone(),
+        encrypted: row.encrypted.as_deref().and_then(|json| {
+            serde_json::from_str(json)
+                .inspect_err(|e| warn!("Entry {} has unreadable encrypted data: {}", row.id, e))
+                .ok()
+        }),
+    }))
+}
+
+/// Persistent queue of guest entries waiting to be submitted to the server.
+pub struct Outbox {
+    db: Db,
+    wake: Notify,
+    policy: RetryPolicy,
+    schedule: Mutex<Schedule>,
+    /// Held from the duplicate check to the insert, so two quick swipes
+    /// can't both pass the check.
+    admission: Mutex<()>,
+}
+
+impl Outbox {
+    pub fn new(db: Db) -> Self {
+        Self {
+            db,
+            wake: Notify::new(),
+            policy: RetryPolicy::default(),
+            schedule: Mutex::new(Schedule::default()),
+            admission: Mutex::new(()),
+        }
+    }
+
+    /// Persist an entry and wake the worker to submit it, unless the same
+    /// card or pass was entered less than `window` ago.
+    pub fn enqueue(
+        &self,
+        entry: Entry,
+        window: Duration,
+        mode: EntryMode,
+    ) -> Result<Enqueued, GuestbookError> {
+        let _admission = self.admission.lock().unwrap();
+        let kind = match &entry {
+            Entry::Card(_) => CARD_KIND,
+            Entry::VisitorPass(_) => VISITOR_PASS_KIND,
+        };
+        let now = Utc::now();
+        let last = self.db.last_entry(kind, entry.id())?;
+        let direction = match admit(last.as_ref(), now, window, mode) {
+            Enqueued::Queued { direction } => direction,
+            duplicate => return Ok(duplicate),
+        };
+        let entry_time = now.to_rfc3339();
+        let stored_direction = direction.map(Direction::as_str);
+        let inserted = match &entry {
+            Entry::Card(card_data) => {
+                let encrypted = card_data
+                    .encrypted
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?;
+                self.db.insert_guest_entry(
+                    &card_data.onecard,
+                    &card_data.name,
+                    &entry_time,
+                    encrypted.as_deref(),
+                    stored_direction,
+                )
+            }
+            Entry::VisitorPass(pass) => self.db.insert_visitor_pass(
+                &pass.pass_id,
+                &pass.name,
+                &entry_time,
+                &serde_json::to_string(pass)?,
+                stored_direction,
+            ),
+        };
+        let id = inserted.map_err(|e| {
+            error!("Failed to queue entry for {}: {}", entry.id(), e);
+            GuestbookError::from(e)
+        })?;
+        match direction {
+            Some(direction) => info!(
+                "Queued entry {} for {} (check-{})",
+                id,
+                entry.id(),
+                direction.as_str()
+            ),
+            None => info!("Queued entry {} for {}", id, entry.id()),
+        }
+        self.wake.notify_one();
+        Ok(Enqueued::Queued { direction })
+    }
+
+    pub fn status(&self) -> Result<OutboxStatus, GuestbookError> {
+        let pending = self.db.count_by_status("pending")?;
+        let dead = self.db.count_by_status("dead")?;
+        let schedule = self.schedule.lock().unwrap();
+        Ok(OutboxStatus {
+            pending,
+            dead,
+            next_retry_at: schedule.next_retry_at.map(|t| t.to_rfc3339()),
+            last_error: schedule.last_error.clone(),
+        })
+    }
+
+    /// Delete dead-lettered entries, and pending ones too if `include_pending`.
+    /// Returns the number of entries removed.
+    pub fn clear(&self, include_pending: bool) -> Result<usize, GuestbookError> {
+        let mut removed = self.db.delete_by_status("dead")?;
+        if include_pending {
+            removed += self.db.delete_by_status("pending")?;
+            self.reset_backoff();
+        }
+        Ok(removed)
+    }
+
+    pub fn last_sent_at(&self) -> Result<Option<String>, GuestbookError> {
+        Ok(self.db.last_sent_at()?)
+    }
+
+    /// Time left before the worker may contact the server again.
+    fn time_until_retry(&self) -> Duration {
+        let schedule = self.schedule.lock().unwrap();
+        schedule
+            .next_retry_at
+            .and_then(|at| (at - Utc::now()).to_std().ok())
+            .unwrap_or(Duration::ZERO)
+    }
+
+    fn reset_backoff(&self) {
+        let mut schedule = self.schedule.lock().unwrap();
+        if schedule.consecutive_failures > 0 {
+            info!(
+                "Outbox delivery recovered after {} failed passes",
+                schedule.consecutive_failures
+            );
+        }
+        *schedule = Schedule::default();
+    }
+
+    fn back_off(&self, reason: String) {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.consecutive_failures = schedule.consecutive_failures.saturating_add(1);
+        let delay = self.policy.delay_with_jitter(schedule.consecutive_failures);
+        let next =
+            Utc::now() + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::zero());
+        info!(
+            "Outbox retry {} scheduled in {}s",
+            schedule.consecutive_failures,
+            delay.as_secs()
+        );
+        schedule.next_retry_at = Some(next);
+        schedule.last_error = Some(reason);
+    }
+
+    /// Move an entry that will never be accepted to the dead letter queue.
+    fn dead_letter(&self, id: i64, message: &str) -> Result<(), String> {
+        self.db
+            .mark_dead(id, message)
+            .map_err(|e| format!("Failed to dead-letter entry {}: {}", id, e))
+    }
+
+    /// Submit pending entries in order and return how many were moved to the
+    /// dead letter queue. Stops at the first failure that should be retried
+    /// later and returns its reason.
+    async fn drain(
+        &self,
+        config_manager: tauri::State<'_, ConfigManager>,
+        api_client: &ApiClient,
+    ) -> Result<u32, String> {
+        let mut dead = 0;
+        loop {
+            let pending = self
+                .db
+                .pending_entries(DRAIN_BATCH_SIZE)
+                .map_err(|e| format!("Failed to read outbox: {}", e))?;
+            if pending.is_empty() {
+                return Ok(dead);
+            }
+            let batch_len = pending.len();
+            for entry in pending {
+                let submission = match stored_entry(&entry) {
+                    Ok(submission) => submission,
+                    Err(message) => {
+                        error!("Entry {} can't be submitted: {}", entry.id, message);
+                        self.dead_letter(entry.id, &message)?;
+                        dead += 1;
+                        continue;
+                    }
+                };
+                let direction = entry.direction.as_deref().and_then(Direction::parse);
+                let result =
+                    with_reregistration(config_manager.clone(), api_client, |config_manager| {
+                        submit_entry(
+                            config_manager,
+                            api_client,
+                            submission.clone(),
+                            &entry.entry_time,
+                            direction,
+                        )
+                    })
+                    .await;
+                let err = match result {
+                    Ok(()) => {
+                        self.db
+                            .mark_sent(entry.id, &Utc::now().to_rfc3339())
+                            .map_err(|e| {
+                                format!("Failed to mark entry {} as sent: {}", entry.id, e)
+                            })?;
+                        continue;
+                    }
+                    Err(err) => err,
+                };
+                let message = err.to_string();
+                match err {
+                    GuestbookError::Http { status, .. }
+                        if classify_status(status) == Disposition::Reject =>
+                    {
+                        warn!(
+                            "Server rejected entry {}, moving to dead letter: {}",
+                            entry.id, message
+                        );
+                        self.dead_letter(entry.id, &message)?;
+                        dead += 1;
+                    }
+                    GuestbookError::Http { .. } => {
+                        let attempts = entry.attempts + 1;
+                        if attempts >= self.policy.max_attempts {
+                            error!(
+                                "Entry {} failed {} times, moving to dead letter: {}",
+                                entry.id, attempts, message
+                            );
+                            self.dead_letter(entry.id, &message)?;
+                            dead += 1;
+                            continue;
+                        }
+                        warn!(
+                            "Submitting entry {} failed (attempt {}): {}",
+                            entry.id, attempts, message
+                        );
+                        self.db.record_failure(entry.id, &message).ok();
+                        return Err(message);
+                    }
+                    // Not the entry's fault (no connection, no token...), so don't count it
+                    _ => {
+                        warn!("Submitting entry {} failed: {}", entry.id, message);
+                        return Err(message);
+                    }
+                }
+            }
+            if batch_len < DRAIN_BATCH_SIZE as usize {
+                return Ok(dead);
+            }
+        }
+    }
+}
+
+/// Queue an entry with the configured duplicate window and entry mode,
+/// emitting `duplicate-entry` instead when it repeats a recent one.
+pub fn record_entry(app: &AppHandle, entry: Entry) -> Result<Enqueued, GuestbookError> {
+    let config = get_full_config(app.state::<ConfigManager>());
+    let window_secs = config
+        .duplicate_window_secs
+        .unwrap_or(DEFAULT_DUPLICATE_WINDOW_SECS);
+    let id = entry.id().to_string();
+    let enqueued = app.state::<Outbox>().enqueue(
+        entry,
+        Duration::from_secs(window_secs),
+        config.entry_mode(),
+    )?;
+    if let Enqueued::Duplicate { last_entry_time } = &enqueued {
+        info!(
+            "Ignoring duplicate entry for {} (last entry at {})",
+            id, last_entry_time
+        );
+        app.emit(
+            "duplicate-entry",
+            DuplicateEntry {
+                id,
+                last_entry_time: last_entry_time.clone(),
+                window_secs,
+            },
+        )
+        .ok();
+    }
+    Ok(enqueued)
+}
+
+#[tauri::command]
+pub fn get_outbox_status(outbox: tauri::State<'_, Outbox>) -> Result<OutboxStatus, GuestbookError> {
+    outbox.status()
+}
+
+/// Spawn the background task that drains the outbox to `/entries/submit`,
+/// backing off exponentially while the server is unreachable.
+pub fn start_outbox_worker(app: AppHandle) {
+    info!("Starting outbox worker");
+    tauri::async_runtime::spawn(async move {
+        let outbox = app.state::<Outbox>();
+        let cutoff = (Utc::now() - chrono::Duration::days(SENT_RETENTION_DAYS)).to_rfc3339();
+        match outbox.db.prune_sent(&cutoff) {
+            Ok(0) => {}
+            Ok(pruned) => info!("Pruned {} delivered entries from the outbox", pruned),
+            Err(e) => warn!("Failed to prune outbox: {}", e),
+        }
+        loop {
+            if outbox.time_until_retry().is_zero() {
+                let drained = outbox
+                    .drain(app.state::<ConfigManager>(), &app.state::<ApiClient>())
+                    .await;
+                match drained {
+                    Ok(dead) => {
+                        if dead > 0 {
+                            warn!("Moved {} entries to the dead letter queue", dead);
+                        }
+                        outbox.reset_backoff();
+                    }
+                    Err(reason) => outbox.back_off(reason),
+                }
+            }
+            // New entries wake us early; during backoff we just go back to sleep
+            let wait = match outbox.time_until_retry() {
+                d if d.is_zero() => IDLE_INTERVAL,
+                d => d,
+            };
+            let _ = tokio::time::timeout(wait, outbox.wake.notified()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last(entry_time: &str, direction: Option<&str>) -> LastEntry {
+        LastEntry {
+            entry_time: entry_time.to_string(),
+            direction: direction.map(str::to_string),
+        }
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn repeats_within_the_window_are_duplicates() {
+        let window = Duration::from_secs(10);
+        let previous = last("2024-01-15T12:00:00+00:00", None);
+        assert_eq!(
+            admit(
+                Some(&previous),
+                at("2024-01-15T12:00:04Z"),
+                window,
+                EntryMode::Entry
+            ),
+            Enqueued::Duplicate {
+                last_entry_time: previous.entry_time.clone()
+            }
+        );
+        for now in ["2024-01-15T12:00:10Z", "2024-01-15T11:59:00Z"] {
+            assert_eq!(
+                admit(Some(&previous), at(now), window, EntryMode::Entry),
+                Enqueued::Queued { direction: None }
+            );
+        }
+        assert_eq!(
+            admit(
+                Some(&previous),
+                at("2024-01-15T12:00:04Z"),
+                Duration::ZERO,
+                EntryMode::Entry
+            ),
+            Enqueued::Queued { direction: None }
+        );
+    }
+
+    #[test]
+    fn check_in_out_alternates_within_a_day() {
+        let window = Duration::from_secs(10);
+        let mode = EntryMode::CheckInOut;
+        let now = at("2024-01-15T12:30:00Z");
+        assert_eq!(
+            admit(None, now, window, mode),
+            Enqueued::Queued {
+                direction: Some(Direction::In)
+            }
+        );
+        let checked_in = last("2024-01-15T12:00:00+00:00", Some("in"));
+        assert_eq!(
+            admit(Some(&checked_in), now, window, mode),
+            Enqueued::Queued {
+                direction: Some(Direction::Out)
+            }
+        );
+        let checked_out = last("2024-01-15T12:20:00+00:00", Some("out"));
+        assert_eq!(
+            admit(Some(&checked_out), now, window, mode),
+            Enqueued::Queued {
+                direction: Some(Direction::In)
+            }
+        );
+        // Never checked out yesterday
+        let yesterday = last("2024-01-14T12:00:00+00:00", Some("in"));
+        assert_eq!(
+            admit(Some(&yesterday), now, window, mode),
+            Enqueued::Queued {
+                direction: Some(Direction::In)
+            }
+        );
+        // The second swipe of a double swipe isn't a check-out
+        assert!(matches!(
+            admit(Some(&checked_in), at("2024-01-15T12:00:03Z"), window, mode),
+            Enqueued::Duplicate { .. }
+        ));
+    }
+
+    #[test]
+    fn commands_tell_queued_entries_from_duplicates() {
+        let queued = serde_json::to_value(Enqueued::Queued {
+            direction: Some(Direction::Out),
+        })
+        .unwrap();
+        assert_eq!(
+            queued,
+            serde_json::json!({ "status": "queued", "direction": "out" })
+        );
+        let duplicate = serde_json::to_value(Enqueued::Duplicate {
+            last_entry_time: "2024-01-15T12:00:00+00:00".to_string(),
+        })
+        .unwrap();
+        assert_eq!(duplicate["status"], "duplicate");
+        assert_ne!(queued["status"], duplicate["status"]);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::api::client::ApiClient;
+use crate::api::devices::{acknowledge_command, upload_log};
+use crate::api::outbox::Outbox;
+use crate::config::config_manager::ConfigManager;
+use crate::error::GuestbookError;
+use crate::{devices, logging};
+use chrono::Local;
+use log::{info, warn, LevelFilter};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// Largest log excerpt sent by `upload_logs`, taken from the end of today's log.
+const MAX_LOG_UPLOAD_BYTES: u64 = 1024 * 1024;
+/// Delay before restarting so pending responses and acks can go out.
+const RESTART_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueScope {
+    /// Only entries the server already rejected.
+    #[default]
+    Dead,
+    /// Dead and pending entries.
+    All,
+}
+
+/// Commands the server can queue for a kiosk; delivered in the heartbeat
+/// response as `{ "id": ..., "type": ..., ...params }`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RemoteCommand {
+    Restart,
+    ReloadConfig,
+    UploadLogs,
+    RescanHid,
+    SetLogLevel {
+        level: String,
+    },
+    ClearQueue {
+        #[serde(default)]
+        scope: QueueScope,
+    },
+}
+
+/// A received command: its id and the parsed command, or why it couldn't be parsed.
+#[derive(Debug)]
+struct Envelope {
+    id: String,
+    command: Result<RemoteCommand, String>,
+}
+
+fn parse_command(value: serde_json::Value) -> Option<Envelope> {
+    let id = match value.get("id") {
+        Some(serde_json::Value::String(id)) => id.clone(),
+        Some(serde_json::Value::Number(id)) => id.to_string(),
+        _ => {
+            warn!("Ignoring remote command without an id: {}", value);
+            return None;
+        }
+    };
+    let command = RemoteCommand::deserialize(value).map_err(|e| e.to_string());
+    Some(Envelope { id, command })
+}
+
+/// Restart the app shortly after returning, so the caller can finish up first.
+pub fn schedule_restart(app: AppHandle) {
+    info!("Initiating application restart...");
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(RESTART_DELAY).await;
+        info!("Executing application restart...");
+        app.restart();
+    });
+}
+
+fn read_tail(path: &Path, max_bytes: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(max_bytes)))?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Run a command and describe the result for the acknowledgement.
+async fn execute(app: &AppHandle, command: &RemoteCommand) -> Result<String, GuestbookError> {
+    match command {
+        // The restart itself waits for the acknowledgement, see `run_commands`
+        RemoteCommand::Restart => Ok("Restarting".to_string()),
+        RemoteCommand::ReloadConfig => {
+            let config = app.state::<ConfigManager>().reload()?;
+            app.state::<ApiClient>().reconfigure(&config)?;
+            Ok("Config reloaded".to_string())
+        }
+        RemoteCommand::UploadLogs => {
+            let config_path = app.state::<ConfigManager>().config_path.clone();
+            let date = Local::now().format("%Y-%m-%d").to_string();
+            let path = logging::log_file(&logging::log_dir(&config_path), &date);
+            let contents = read_tail(&path, MAX_LOG_UPLOAD_BYTES).map_err(|e| {
+                GuestbookError::Storage(format!("Failed to read log file {:?}: {}", path, e))
+            })?;
+            let size = contents.len();
+            let file_name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            upload_log(
+                app.state::<ConfigManager>(),
+                &app.state::<ApiClient>(),
+                &file_name,
+                contents,
+            )
+            .await?;
+            Ok(format!("Uploaded {} bytes of {}", size, file_name))
+        }
+        RemoteCommand::RescanHid => {
+            let running = devices::rescan_readers(app);
+            Ok(format!("Readers running: [{}]", running.join(", ")))
+        }
+        RemoteCommand::SetLogLevel { level } => {
+            let filter: LevelFilter = level
+                .parse()
+                .map_err(|_| GuestbookError::Parse(format!("Unknown log level {:?}", level)))?;
+            log::set_max_level(filter);
+            Ok(format!("Log level set to {}", filter))
+        }
+        RemoteCommand::ClearQueue { scope } => {
+            let removed = app.state::<Outbox>().clear(*scope == QueueScope::All)?;
+            Ok(format!("Removed {} queued entries", removed))
+        }
+    }
+}
+
+async fn acknowledge(app: &AppHandle, id: &str, status: &str, message: &str) -> bool {
+    let result = acknowledge_command(
+        app.state::<ConfigManager>(),
+        &app.state::<ApiClient>(),
+        id,
+        status,
+        message,
+    )
+    .await;
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to acknowledge remote command {}: {}", id, e);
+            false
+        }
+    }
+}
+
+/// Execute the commands from a heartbeat response in order and acknowledge
+/// each one with `ok`, `failed` or `unsupported`.
+pub async fn run_commands(app: &AppHandle, commands: Vec<serde_json::Value>) {
+    for Envelope { id, command } in commands.into_iter().filter_map(parse_command) {
+        let command = match command {
+            Ok(command) => command,
+            Err(reason) => {
+                warn!("Unsupported remote command {}: {}", id, reason);
+                acknowledge(app, &id, "unsupported", &reason).await;
+                continue;
+            }
+        };
+        info!("Running remote command {}: {:?}", id, command);
+        let (status, message) = match execute(app, &command).await {
+            Ok(message) => ("ok", message),
+            Err(e) => {
+                warn!("Remote command {} failed: {}", id, e);
+                ("failed", e.to_string())
+            }
+        };
+        let acknowledged = acknowledge(app, &id, status, &message).await;
+        // Unacknowledged commands are sent again, so only restart once the
+        // server knows; anything after this runs on the next heartbeat.
+        if command == RemoteCommand::Restart && acknowledged {
+            schedule_restart(app.clone());
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_commands_and_keeps_unknown_ones_for_acknowledgement() {
+        let commands = vec![
+            json!({ "id": "c1", "type": "restart" }),
+            json!({ "id": 2, "type": "set_log_level", "level": "warn" }),
+            json!({ "id": "c3", "type": "clear_queue" }),
+            json!({ "id": "c4", "type": "clear_queue", "scope": "all" }),
+            json!({ "id": "c5", "type": "format_disk" }),
+            json!({ "type": "rescan_hid" }),
+        ];
+        let parsed: Vec<Envelope> = commands.into_iter().filter_map(parse_command).collect();
+
+        assert_eq!(parsed.len(), 5);
+        assert_eq!(parsed[0].command, Ok(RemoteCommand::Restart));
+        assert_eq!(parsed[1].id, "2");
+        assert_eq!(
+            parsed[1].command,
+            Ok(RemoteCommand::SetLogLevel {
+                level: "warn".into()
+            })
+        );
+        assert_eq!(
+            parsed[2].command,
+            Ok(RemoteCommand::ClearQueue {
+                scope: QueueScope::Dead
+            })
+        );
+        assert_eq!(
+            parsed[3].command,
+            Ok(RemoteCommand::ClearQueue {
+                scope: QueueScope::All
+            })
+        );
+        assert_eq!(parsed[4].id, "c5");
+        assert!(parsed[4].command.is_err());
+    }
+}
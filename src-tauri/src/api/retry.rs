@@ -0,0 +1,99 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// Exponential backoff settings for resubmitting queued entries.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Failed attempts after which an entry is moved to the dead letter state.
+    pub max_attempts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            base_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(15 * 60),
+            max_attempts: 10,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff before the next attempt after `failures` consecutive failures,
+    /// without jitter. Doubles each time and is capped at `max_delay`.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 1u32.checked_shl(failures - 1).unwrap_or(u32::MAX);
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// `backoff` with "equal jitter": half the delay is fixed, the other half
+    /// random, so kiosks that lost the network together don't retry in lockstep.
+    pub fn delay_with_jitter(&self, failures: u32) -> Duration {
+        let delay = self.backoff(failures);
+        let half = delay / 2;
+        let jitter_ms = rand::rng().random_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter_ms)
+    }
+}
+
+/// What to do with an entry after the server answered with a non-success status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    /// Temporary problem on the server or with our credentials; try again later.
+    Retry,
+    /// The server will never accept this entry; move it to the dead letter state.
+    Reject,
+}
+
+pub fn classify_status(status: u16) -> Disposition {
+    match status {
+        // Timeouts, rate limiting and auth problems aren't the entry's fault
+        401 | 403 | 408 | 429 => Disposition::Retry,
+        400..=499 => Disposition::Reject,
+        _ => Disposition::Retry,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_and_is_capped() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::ZERO);
+        assert_eq!(policy.backoff(1), Duration::from_secs(5));
+        assert_eq!(policy.backoff(2), Duration::from_secs(10));
+        assert_eq!(policy.backoff(4), Duration::from_secs(40));
+        assert_eq!(policy.backoff(20), policy.max_delay);
+        assert_eq!(policy.backoff(u32::MAX), policy.max_delay);
+    }
+
+    #[test]
+    fn jitter_stays_within_half_to_full_delay() {
+        let policy = RetryPolicy::default();
+        for failures in 1..12 {
+            let delay = policy.delay_with_jitter(failures);
+            let full = policy.backoff(failures);
+            assert!(delay >= full / 2 && delay <= full);
+        }
+    }
+
+    #[test]
+    fn client_errors_are_rejected_but_server_errors_retried() {
+        assert_eq!(classify_status(400), Disposition::Reject);
+        assert_eq!(classify_status(422), Disposition::Reject);
+        assert_eq!(classify_status(401), Disposition::Retry);
+        assert_eq!(classify_status(429), Disposition::Retry);
+        assert_eq!(classify_status(500), Disposition::Retry);
+        assert_eq!(classify_status(503), Disposition::Retry);
+    }
+}
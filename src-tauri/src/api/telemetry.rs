@@ -0,0 +1,148 @@
+use crate::api::heartbeat::HeartbeatMonitor;
+use crate::api::outbox::Outbox;
+use crate::config::config_manager::ConfigManager;
+use crate::devices::{reader_states, ReaderState};
+use crate::{hid, logging};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+#[derive(Serialize, Clone, Debug)]
+pub struct HidDeviceSummary {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct InterfaceAddress {
+    pub interface: String,
+    pub ip: String,
+}
+
+/// Health snapshot POSTed with every heartbeat so the server dashboard can
+/// spot kiosks with a detached reader, a growing outbox or a full disk.
+#[derive(Serialize, Clone, Debug)]
+pub struct Telemetry {
+    pub app_version: String,
+    pub uptime_secs: u64,
+    pub outbox_pending: u32,
+    pub outbox_dead: u32,
+    pub last_submission_at: Option<String>,
+    pub hid_devices: Vec<HidDeviceSummary>,
+    pub readers: BTreeMap<&'static str, ReaderState>,
+    pub ip_addresses: Vec<InterfaceAddress>,
+    pub free_disk_bytes: Option<u64>,
+    pub log_size_bytes: u64,
+}
+
+/// Parts of the snapshot that need blocking system calls.
+#[derive(Default)]
+struct HostTelemetry {
+    hid_devices: Vec<HidDeviceSummary>,
+    ip_addresses: Vec<InterfaceAddress>,
+    free_disk_bytes: Option<u64>,
+    log_size_bytes: u64,
+}
+
+fn host_telemetry(config_path: &Path) -> HostTelemetry {
+    let data_dir = config_path.parent().unwrap_or(Path::new("."));
+    HostTelemetry {
+        hid_devices: hid::list_devices()
+            .iter()
+            .map(|d| HidDeviceSummary {
+                vendor_id: d.vendor_id(),
+                product_id: d.product_id(),
+                manufacturer: d.manufacturer_string().map(str::to_string),
+                product: d.product_string().map(str::to_string),
+            })
+            .collect(),
+        ip_addresses: ip_addresses(),
+        free_disk_bytes: free_disk_bytes(data_dir),
+        log_size_bytes: dir_size(&logging::log_dir(config_path)),
+    }
+}
+
+pub async fn collect_telemetry(app: &AppHandle) -> Telemetry {
+    let outbox = app.state::<Outbox>();
+    let (outbox_pending, outbox_dead) = match outbox.status() {
+        Ok(status) => (status.pending, status.dead),
+        Err(e) => {
+            log::warn!("Failed to read outbox status for telemetry: {}", e);
+            (0, 0)
+        }
+    };
+    let config_path = app.state::<ConfigManager>().config_path.clone();
+    // A slow disk or network interface must not stall the async runtime
+    let host = tauri::async_runtime::spawn_blocking(move || host_telemetry(&config_path))
+        .await
+        .unwrap_or_else(|e| {
+            log::warn!("Failed to collect host telemetry: {}", e);
+            HostTelemetry::default()
+        });
+
+    Telemetry {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: app.state::<HeartbeatMonitor>().uptime().as_secs(),
+        outbox_pending,
+        outbox_dead,
+        last_submission_at: outbox.last_sent_at().unwrap_or_default(),
+        hid_devices: host.hid_devices,
+        readers: reader_states(),
+        ip_addresses: host.ip_addresses,
+        free_disk_bytes: host.free_disk_bytes,
+        log_size_bytes: host.log_size_bytes,
+    }
+}
+
+fn ip_addresses() -> Vec<InterfaceAddress> {
+    match get_if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces
+            .into_iter()
+            .filter(|i| !i.is_loopback())
+            .map(|i| InterfaceAddress {
+                ip: i.ip().to_string(),
+                interface: i.name,
+            })
+            .collect(),
+        Err(e) => {
+            log::warn!("Failed to list network interfaces: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok()?.metadata().ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+#[allow(clippy::useless_conversion)] // statvfs field widths differ between platforms
+fn free_disk_bytes(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is NUL-terminated and stat is a valid out pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(u64::from(stat.f_bavail) * u64::from(stat.f_frsize))
+}
+
+#[cfg(not(unix))]
+fn free_disk_bytes(_path: &Path) -> Option<u64> {
+    None
+}
@@ -1,5 +1,12 @@
+use crate::api::entries::EntryMode;
 use crate::config::device_id::compute_device_id;
-use serde::{Deserialize, Serialize};
+use crate::devices::framing::BarcodeFraming;
+use crate::devices::normalize::{default_formats, BarcodeFormat};
+use crate::devices::profiles::{ProfileEntry, ONECARD_PROFILE};
+use crate::devices::registry::{default_registry, ReaderMatch};
+use crate::error::GuestbookError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
@@ -14,6 +21,56 @@ pub struct Config {
     pub device_location: Option<String>,
     pub device_friendly_name: Option<String>,
     pub first_run: bool,
+    /// Proxy for all API requests, e.g. `http://proxy.example.edu:3128`.
+    pub http_proxy: Option<String>,
+    pub connect_timeout_secs: Option<u64>,
+    pub request_timeout_secs: Option<u64>,
+    pub heartbeat_interval_secs: Option<u64>,
+    /// HID readers to use, tried in order; the built-in registry when unset.
+    #[serde(default, deserialize_with = "lenient")]
+    pub readers: Option<Vec<ReaderMatch>>,
+    /// Card profiles tried in order on each swipe; just `onecard` when unset.
+    #[serde(default, deserialize_with = "lenient")]
+    pub card_profiles: Option<Vec<ProfileEntry>>,
+    /// How barcodes are cut out of scanner input; unset fields use the defaults.
+    #[serde(default, deserialize_with = "lenient")]
+    pub barcode_framing: Option<BarcodeFraming>,
+    /// Barcode layouts tried in order to find the OneCard ID; the built-in
+    /// formats when unset.
+    #[serde(default, deserialize_with = "lenient")]
+    pub barcode_formats: Option<Vec<BarcodeFormat>>,
+    /// Base64 Ed25519 public key that visitor pass QR codes are signed with.
+    pub visitor_pass_public_key: Option<String>,
+    /// Record the raw input of every reader to `captures/` next to this
+    /// file, for replaying reader bugs. Captures hold unmasked card data.
+    pub capture_readers: Option<bool>,
+    /// Replace every reader with a simulated one that takes input from the
+    /// `simulate_reader_input` command and a loopback socket, for development.
+    pub simulated_readers: Option<bool>,
+    /// Seconds during which the same card or pass is ignored after an
+    /// entry; 10 when unset, 0 turns it off.
+    pub duplicate_window_secs: Option<u64>,
+    /// `check_in_out` for venues where the next presentation of a card
+    /// means leaving; plain entries when unset.
+    #[serde(default, deserialize_with = "lenient")]
+    pub entry_mode: Option<EntryMode>,
+}
+
+/// Deserialize an optional section, dropping it with a warning when it is
+/// invalid so one bad entry doesn't cost the rest of the config.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value.and_then(|value| match serde_json::from_value(value) {
+        Ok(section) => Some(section),
+        Err(e) => {
+            log::warn!("Ignoring invalid config section: {}", e);
+            None
+        }
+    }))
 }
 
 impl Default for Config {
@@ -25,10 +82,73 @@ impl Default for Config {
             device_location: None,
             device_friendly_name: None,
             first_run: true,
+            http_proxy: None,
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            heartbeat_interval_secs: None,
+            readers: None,
+            card_profiles: None,
+            barcode_framing: None,
+            barcode_formats: None,
+            visitor_pass_public_key: None,
+            capture_readers: None,
+            simulated_readers: None,
+            duplicate_window_secs: None,
+            entry_mode: None,
         }
     }
 }
 
+impl Config {
+    pub fn require_server_url(&self) -> Result<&str, GuestbookError> {
+        self.server_url
+            .as_deref()
+            .ok_or_else(|| GuestbookError::Config("Server URL is not configured".to_string()))
+    }
+
+    pub fn require_server_token(&self) -> Result<&str, GuestbookError> {
+        self.server_token
+            .as_deref()
+            .ok_or(GuestbookError::NotRegistered)
+    }
+
+    pub fn reader_registry(&self) -> Vec<ReaderMatch> {
+        self.readers.clone().unwrap_or_else(default_registry)
+    }
+
+    pub fn card_profiles(&self) -> Vec<ProfileEntry> {
+        self.card_profiles
+            .clone()
+            .unwrap_or_else(|| vec![ProfileEntry::Builtin(ONECARD_PROFILE.to_string())])
+    }
+
+    pub fn barcode_framing(&self) -> BarcodeFraming {
+        self.barcode_framing.clone().unwrap_or_default()
+    }
+
+    pub fn barcode_formats(&self) -> Vec<BarcodeFormat> {
+        self.barcode_formats.clone().unwrap_or_else(default_formats)
+    }
+
+    pub fn capture_readers(&self) -> bool {
+        self.capture_readers.unwrap_or(false)
+    }
+
+    pub fn simulated_readers(&self) -> bool {
+        self.simulated_readers.unwrap_or(false)
+    }
+
+    pub fn entry_mode(&self) -> EntryMode {
+        self.entry_mode.unwrap_or_default()
+    }
+
+    pub fn require_device_id(&self) -> Result<&str, GuestbookError> {
+        self.device_id
+            .as_deref()
+            .ok_or_else(|| GuestbookError::Config("Device ID is not set".to_string()))
+    }
+}
+
 pub struct ConfigManager {
     pub config_path: PathBuf,
     pub config: Arc<Mutex<Config>>,
@@ -43,12 +163,18 @@ impl ConfigManager {
             }
         }
         let config = Self::load_config(&config_path);
+        // Don't replace a config that exists but can't be read with defaults
+        let unreadable = config.is_none() && config_path.exists();
         let merged_config = Self::merge_with_default(config);
         let manager = Self {
             config_path,
             config: Arc::new(Mutex::new(merged_config)),
         };
-        manager.save_config().ok();
+        if unreadable {
+            manager.back_up_config();
+        } else {
+            manager.save_config().ok();
+        }
         manager
     }
 
@@ -65,13 +191,27 @@ impl ConfigManager {
 
     fn load_config(path: &Path) -> Option<Config> {
         if path.exists() {
-            let data = fs::read_to_string(path).ok()?;
-            serde_json::from_str(&data).ok()
+            let data = fs::read_to_string(path)
+                .map_err(|e| log::error!("Failed to read {:?}: {}", path, e))
+                .ok()?;
+            serde_json::from_str(&data)
+                .map_err(|e| log::error!("Failed to parse {:?}: {}", path, e))
+                .ok()
         } else {
             None
         }
     }
 
+    /// Copy an unreadable config file aside, so saving a setting later
+    /// doesn't lose what was in it.
+    fn back_up_config(&self) {
+        let backup = self.config_path.with_extension("json.bak");
+        match fs::copy(&self.config_path, &backup) {
+            Ok(_) => log::warn!("Kept unreadable config as {:?}", backup),
+            Err(e) => log::error!("Failed to back up {:?}: {}", self.config_path, e),
+        }
+    }
+
     fn merge_with_default(config: Option<Config>) -> Config {
         let mut default = Config::default();
         if let Some(cfg) = config {
@@ -92,6 +232,19 @@ impl ConfigManager {
                 default.device_friendly_name = Some(device_friendly_name);
             }
             default.first_run = cfg.first_run;
+            default.http_proxy = cfg.http_proxy;
+            default.connect_timeout_secs = cfg.connect_timeout_secs;
+            default.request_timeout_secs = cfg.request_timeout_secs;
+            default.heartbeat_interval_secs = cfg.heartbeat_interval_secs;
+            default.readers = cfg.readers;
+            default.card_profiles = cfg.card_profiles;
+            default.barcode_framing = cfg.barcode_framing;
+            default.barcode_formats = cfg.barcode_formats;
+            default.visitor_pass_public_key = cfg.visitor_pass_public_key;
+            default.capture_readers = cfg.capture_readers;
+            default.simulated_readers = cfg.simulated_readers;
+            default.duplicate_window_secs = cfg.duplicate_window_secs;
+            default.entry_mode = cfg.entry_mode;
         }
         default
     }
@@ -118,6 +271,17 @@ impl ConfigManager {
         let _ = self.save_config();
     }
 
+    /// Re-read the config file, e.g. after it was edited on disk. The current
+    /// config is kept if the file is missing or can't be parsed.
+    pub fn reload(&self) -> Result<Config, GuestbookError> {
+        let loaded = Self::load_config(&self.config_path).ok_or_else(|| {
+            GuestbookError::Config(format!("Could not read config file {:?}", self.config_path))
+        })?;
+        let merged = Self::merge_with_default(Some(loaded));
+        *self.config.lock().unwrap() = merged.clone();
+        Ok(merged)
+    }
+
     pub fn set_server_token(&self, server_token: String) {
         self.set(server_token, |c, v| c.server_token = Some(v));
     }
@@ -130,3 +294,29 @@ pub fn get_full_config(config_manager: State<'_, ConfigManager>) -> Config {
     let config = config_manager.config.lock().unwrap().clone();
     config
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_invalid_reader_entry_keeps_the_rest_of_the_config() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "server_url": "https://guestbook.example.edu/api/v1",
+                "server_token": "secret",
+                "device_id": "kiosk-1",
+                "first_run": false,
+                "readers": [{ "role": "swipe", "vendor_id": "magtek" }],
+                "duplicate_window_secs": 5
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.server_token.as_deref(), Some("secret"));
+        assert_eq!(config.device_id.as_deref(), Some("kiosk-1"));
+        assert!(!config.first_run);
+        assert!(config.readers.is_none());
+        assert_eq!(config.duplicate_window_secs, Some(5));
+        assert_eq!(config.reader_registry(), default_registry());
+    }
+}
@@ -1,38 +1,406 @@
-use rusqlite::{params, Connection, Result};
-use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// `kind` of entries made with a card swipe or barcode.
+pub const CARD_KIND: &str = "card";
+/// `kind` of entries made with a visitor pass; `onecard` holds the pass ID.
+pub const VISITOR_PASS_KIND: &str = "visitor_pass";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuestEntry {
-    pub id: i32,
-    pub onecard: i32,
+    pub id: i64,
+    pub onecard: String,
+    pub name: String,
     pub entry_time: String,
-    pub name: Option<String>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// JSON of the `EncryptedSwipe` from a MagneSafe reader, if any.
+    pub encrypted: Option<String>,
+    pub kind: String,
+    /// JSON of the `VisitorPass` for visitor pass entries.
+    pub pass: Option<String>,
+    /// `in` or `out` for entries made in check-in/check-out mode.
+    pub direction: Option<String>,
+}
 
+/// The most recent entry for a card or pass, whatever its status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastEntry {
+    pub entry_time: String,
+    pub direction: Option<String>,
 }
 
+/// Local SQLite store used as the outbox for guest entries.
+///
+/// Every swipe/scan is written here first and only marked as sent once the
+/// server has accepted it, so entries survive network outages and restarts.
 pub struct Db {
-    conn: Connection,
+    conn: Mutex<Connection>,
 }
 
 impl Db {
-    pub fn new(path: &str) -> Result<Self> {
-        let conn = Connection::open(path)?;
+    pub fn new(path: &Path) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
         conn.execute(
             "CREATE TABLE IF NOT EXISTS guest_entries (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
-                onecard INTEGER,
-                name TEXT,
-                entry_time TEXT
+                onecard TEXT NOT NULL,
+                name TEXT NOT NULL,
+                entry_time TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                sent_at TEXT,
+                encrypted TEXT,
+                kind TEXT NOT NULL DEFAULT 'card',
+                pass TEXT,
+                direction TEXT
             )",
+            [],
+        )?;
+        // Columns added since the first release, missing from older databases
+        for (column, definition) in [
+            ("encrypted", "TEXT"),
+            ("kind", "TEXT NOT NULL DEFAULT 'card'"),
+            ("pass", "TEXT"),
+            ("direction", "TEXT"),
+        ] {
+            let exists = conn
+                .prepare("SELECT 1 FROM pragma_table_info('guest_entries') WHERE name = ?1")?
+                .exists(params![column])?;
+            if !exists {
+                conn.execute(
+                    &format!(
+                        "ALTER TABLE guest_entries ADD COLUMN {} {}",
+                        column, definition
+                    ),
+                    [],
+                )?;
+            }
+        }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_guest_entries_status ON guest_entries (status, id)",
+            [],
         )?;
-        Ok(Self { conn })
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_guest_entries_card ON guest_entries (kind, onecard, id)",
+            [],
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
     }
 
-    pub fn insert_guest_entry(&self, entry: &GuestEntry) -> Result<()> {
-        self.conn.execute(
-            "INSERT INTO guest_entries (name, entry_time, onecard) VALUES (?, ?, ?)",
-            params![entry.onecard, entry.name, entry.entry_time],
+    /// Queue a new entry for submission and return its row id.
+    pub fn insert_guest_entry(
+        &self,
+        onecard: &str,
+        name: &str,
+        entry_time: &str,
+        encrypted: Option<&str>,
+        direction: Option<&str>,
+    ) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO guest_entries (onecard, name, entry_time, encrypted, kind, direction) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![onecard, name, entry_time, encrypted, CARD_KIND, direction],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Queue a visitor pass entry, keeping the pass JSON for submission.
+    pub fn insert_visitor_pass(
+        &self,
+        pass_id: &str,
+        name: &str,
+        entry_time: &str,
+        pass: &str,
+        direction: Option<&str>,
+    ) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO guest_entries (onecard, name, entry_time, kind, pass, direction) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![pass_id, name, entry_time, VISITOR_PASS_KIND, pass, direction],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Oldest pending entries first, so the server receives them in swipe order.
+    pub fn pending_entries(&self, limit: u32) -> Result<Vec<GuestEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, onecard, name, entry_time, attempts, last_error, encrypted, kind, pass, direction
+             FROM guest_entries
+             WHERE status = 'pending'
+             ORDER BY id
+             LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit], |row| {
+            Ok(GuestEntry {
+                id: row.get(0)?,
+                onecard: row.get(1)?,
+                name: row.get(2)?,
+                entry_time: row.get(3)?,
+                attempts: row.get(4)?,
+                last_error: row.get(5)?,
+                encrypted: row.get(6)?,
+                kind: row.get(7)?,
+                pass: row.get(8)?,
+                direction: row.get(9)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Latest entry of `kind` for `onecard`. Sent entries are kept for weeks,
+    /// so this survives restarts and delivery. Entries the server rejected
+    /// were never recorded, so they don't count.
+    pub fn last_entry(&self, kind: &str, onecard: &str) -> Result<Option<LastEntry>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT entry_time, direction FROM guest_entries
+             WHERE kind = ?1 AND onecard = ?2 AND status != 'dead'
+             ORDER BY id DESC
+             LIMIT 1",
+            params![kind, onecard],
+            |row| {
+                Ok(LastEntry {
+                    entry_time: row.get(0)?,
+                    direction: row.get(1)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    pub fn mark_sent(&self, id: i64, sent_at: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE guest_entries SET status = 'sent', sent_at = ?2, last_error = NULL WHERE id = ?1",
+            params![id, sent_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_failure(&self, id: i64, error: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE guest_entries SET attempts = attempts + 1, last_error = ?2 WHERE id = ?1",
+            params![id, error],
+        )?;
+        Ok(())
+    }
+
+    /// Move an entry the server will never accept out of the pending queue.
+    pub fn mark_dead(&self, id: i64, error: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE guest_entries SET status = 'dead', attempts = attempts + 1, last_error = ?2 WHERE id = ?1",
+            params![id, error],
         )?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    pub fn count_by_status(&self, status: &str) -> Result<u32> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COUNT(*) FROM guest_entries WHERE status = ?1",
+            params![status],
+            |row| row.get(0),
+        )
+    }
+
+    /// Time the server last accepted an entry, if ever.
+    pub fn last_sent_at(&self) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT MAX(sent_at) FROM guest_entries WHERE status = 'sent'",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// Delete every entry with the given status and return how many were removed.
+    pub fn delete_by_status(&self, status: &str) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM guest_entries WHERE status = ?1",
+            params![status],
+        )
+    }
+
+    /// Drop delivered entries older than `before` so the database doesn't grow forever.
+    pub fn prune_sent(&self, before: &str) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM guest_entries WHERE status = 'sent' AND sent_at < ?1",
+            params![before],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_entries_are_returned_in_order_until_sent() {
+        let db = Db::open_in_memory().unwrap();
+        let first = db
+            .insert_guest_entry("0012345", "DOE/JANE", "2024-01-15T14:30:25Z", None, None)
+            .unwrap();
+        db.insert_guest_entry(
+            "7654321",
+            "Barcode",
+            "2024-01-15T14:31:00Z",
+            Some(r#"{"ksn":"FFFF"}"#),
+            None,
+        )
+        .unwrap();
+
+        let pending = db.pending_entries(10).unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].onecard, "0012345");
+        assert_eq!(pending[0].encrypted, None);
+        assert_eq!(pending[1].encrypted.as_deref(), Some(r#"{"ksn":"FFFF"}"#));
+
+        db.record_failure(first, "connection refused").unwrap();
+        let pending = db.pending_entries(10).unwrap();
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(pending[0].last_error.as_deref(), Some("connection refused"));
+
+        db.mark_sent(first, "2024-01-15T14:35:00Z").unwrap();
+        let pending = db.pending_entries(10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].onecard, "7654321");
+    }
+
+    #[test]
+    fn dead_entries_leave_the_pending_queue() {
+        let db = Db::open_in_memory().unwrap();
+        let id = db
+            .insert_guest_entry("abc", "Barcode", "2024-01-15T14:30:25Z", None, None)
+            .unwrap();
+        db.mark_dead(id, "status 422").unwrap();
+
+        assert!(db.pending_entries(10).unwrap().is_empty());
+        assert_eq!(db.count_by_status("pending").unwrap(), 0);
+        assert_eq!(db.count_by_status("dead").unwrap(), 1);
+    }
+
+    #[test]
+    fn visitor_passes_are_queued_with_their_kind() {
+        let db = Db::open_in_memory().unwrap();
+        db.insert_guest_entry("1234567", "DOE/JANE", "2024-01-15T14:30:25Z", None, None)
+            .unwrap();
+        db.insert_visitor_pass("V-1", "PAT GUEST", "2024-01-15T14:31:00Z", "{}", None)
+            .unwrap();
+
+        let pending = db.pending_entries(10).unwrap();
+        assert_eq!(pending[0].kind, CARD_KIND);
+        assert_eq!(pending[0].pass, None);
+        assert_eq!(pending[1].kind, VISITOR_PASS_KIND);
+        assert_eq!(pending[1].onecard, "V-1");
+        assert_eq!(pending[1].pass.as_deref(), Some("{}"));
+    }
+
+    #[test]
+    fn adds_new_columns_to_existing_databases() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE guest_entries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                onecard TEXT NOT NULL,
+                name TEXT NOT NULL,
+                entry_time TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                sent_at TEXT
+            )",
+            [],
+        )
+        .unwrap();
+        let db = Db::init(conn).unwrap();
+        db.insert_guest_entry(
+            "1234567",
+            "DOE/JANE",
+            "2024-01-15T14:30:25Z",
+            Some("{}"),
+            Some("in"),
+        )
+        .unwrap();
+        let entry = &db.pending_entries(1).unwrap()[0];
+        assert_eq!(entry.encrypted.as_deref(), Some("{}"));
+        assert_eq!(entry.kind, CARD_KIND);
+        assert_eq!(entry.direction.as_deref(), Some("in"));
+    }
+
+    #[test]
+    fn last_entry_outlives_delivery() {
+        let db = Db::open_in_memory().unwrap();
+        assert_eq!(db.last_entry(CARD_KIND, "1234567").unwrap(), None);
+        let first = db
+            .insert_guest_entry(
+                "1234567",
+                "DOE/JANE",
+                "2024-01-15T14:30:25Z",
+                None,
+                Some("in"),
+            )
+            .unwrap();
+        db.insert_visitor_pass("1234567", "PAT GUEST", "2024-01-15T14:31:00Z", "{}", None)
+            .unwrap();
+        db.mark_sent(first, "2024-01-15T14:30:30Z").unwrap();
+
+        assert_eq!(
+            db.last_entry(CARD_KIND, "1234567").unwrap(),
+            Some(LastEntry {
+                entry_time: "2024-01-15T14:30:25Z".to_string(),
+                direction: Some("in".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn rejected_entries_are_not_the_last_entry() {
+        let db = Db::open_in_memory().unwrap();
+        let sent = db
+            .insert_guest_entry(
+                "1234567",
+                "DOE/JANE",
+                "2024-01-15T09:00:00Z",
+                None,
+                Some("in"),
+            )
+            .unwrap();
+        db.mark_sent(sent, "2024-01-15T09:00:05Z").unwrap();
+        let rejected = db
+            .insert_guest_entry(
+                "1234567",
+                "DOE/JANE",
+                "2024-01-15T14:30:25Z",
+                None,
+                Some("out"),
+            )
+            .unwrap();
+        db.mark_dead(rejected, "HTTP 422").unwrap();
+
+        assert_eq!(
+            db.last_entry(CARD_KIND, "1234567").unwrap(),
+            Some(LastEntry {
+                entry_time: "2024-01-15T09:00:00Z".to_string(),
+                direction: Some("in".to_string()),
+            })
+        );
+    }
+}
@@ -1,3 +1,4 @@
-#[cfg(feature = "db")]
+#[allow(clippy::module_inception)]
 pub mod db;
-use db::Db;
\ No newline at end of file
+
+pub use db::{Db, GuestEntry, LastEntry, CARD_KIND, VISITOR_PASS_KIND};
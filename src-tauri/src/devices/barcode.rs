@@ -1,73 +1,121 @@
-use hidapi::{HidApi, HidDevice};
+use crate::config::config_manager::Config;
+use crate::devices::dispatcher::{EventSink, ReaderDecoder};
+use crate::devices::framing::{BarcodeFramer, BarcodeFraming};
+use crate::devices::hid_pos::{PosReportAssembler, Symbology};
+use crate::devices::keyboard::BootKeyboardDecoder;
+use crate::devices::normalize::{barcode_id, BarcodeFormat};
+use crate::devices::registry::{ReaderMatch, ReportFormat};
+use crate::devices::visitor_pass::{configured_key, parse_visitor_pass, ScannedPass};
+use chrono::Utc;
+use ed25519_dalek::VerifyingKey;
+use serde::Serialize;
 use std::time::{Duration, Instant};
-use tauri::{Emitter, Window};
-use log::{info, warn, error};
+use log::{info, warn};
 
-pub fn listen_to_barcode(device: HidDevice, window: Window) {
-    info!("Starting barcode scanner listener thread");
-    std::thread::spawn(move || {
-        let mut buffer = [0u8; 64];
-        let mut scan_buffer = String::new();
-        let mut last_char_time = Instant::now();
-        let mut consecutive_errors = 0;
-        let max_consecutive_errors = 5;
-
-        loop {
-            match device.read(&mut buffer) {
-                Ok(size) => {
-                    consecutive_errors = 0; // Reset error counter on successful read
-                    let now = Instant::now();
-                    let raw_data = &buffer[..size];
-                    let part = String::from_utf8_lossy(raw_data);
-
-                    // If too much time passed between characters, flush early
-                    if now.duration_since(last_char_time) > Duration::from_millis(150) {
-                        scan_buffer.clear();
-                    }
+/// Payload of the `barcode-data` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct BarcodeScan {
+    pub value: String,
+    /// Only known for HID POS scanners.
+    pub symbology: Symbology,
+}
 
-                    scan_buffer.push_str(&part);
-                    last_char_time = now;
+fn emit_scan(sink: &dyn EventSink, value: String, symbology: Symbology) {
+    info!("Barcode scanned: {} ({:?})", value, symbology);
+    sink.emit("barcode-data", BarcodeScan { value, symbology });
+}
 
-                    // Attempt auto-flush if it looks like a 7–9 digit scan
-                    let cleaned = scan_buffer.replace(|c: char| !c.is_ascii_digit(), "");
+/// What a framed barcode is checked against, from the config.
+struct ScanRules {
+    formats: Vec<BarcodeFormat>,
+    pass_key: Option<VerifyingKey>,
+}
 
-                    if cleaned.len() == 7 || cleaned.len() == 9 {
-                        info!("Barcode scanned: {}", cleaned);
-                        window.emit("barcode-data", cleaned.clone()).ok();
-                        scan_buffer.clear();
-                    }
-                }
-                Err(e) => {
-                    consecutive_errors += 1;
-                    warn!("Barcode scanner read error (attempt {}/{}): {}", consecutive_errors, max_consecutive_errors, e);
+/// Emit a framed barcode as a visitor pass or a normalized card ID, or log
+/// why it was dropped.
+fn handle_barcode(sink: &dyn EventSink, rules: &ScanRules, framed: Result<String, String>, symbology: Symbology) {
+    let data = match framed {
+        Ok(data) => data,
+        Err(reason) => {
+            warn!("Discarding barcode: {}", reason);
+            return;
+        }
+    };
+    match parse_visitor_pass(&data, rules.pass_key.as_ref(), Utc::now()) {
+        Some(Ok(pass)) => {
+            info!("Visitor pass scanned: {} ({})", pass.pass_id, pass.name);
+            sink.emit("visitor-pass", ScannedPass { pass, token: data });
+        }
+        Some(Err(rejection)) => {
+            warn!("Rejecting visitor pass: {}", rejection);
+            sink.emit("visitor-pass-rejected", rejection.to_string());
+        }
+        None => match barcode_id(&rules.formats, &data) {
+            Some((id, format)) => {
+                log::debug!("Barcode {} read as {} ({} format)", data, id, format);
+                emit_scan(sink, id, symbology);
+            }
+            None => warn!("Discarding {:?} barcode that is not a OneCard ID: {}", symbology, data),
+        },
+    }
+}
 
-                    if consecutive_errors >= max_consecutive_errors {
-                        error!("Barcode scanner failed after {} consecutive errors, stopping listener", max_consecutive_errors);
-                        window.emit("hid-error", format!("Barcode scanner failed: {}", e)).ok();
-                        break;
-                    }
+/// Decodes a scanner's reports, frames them into barcodes and emits each
+/// one as a visitor pass or card ID.
+pub struct BarcodeDecoder {
+    rules: ScanRules,
+    framing: BarcodeFraming,
+    framer: BarcodeFramer,
+    keyboard: Option<BootKeyboardDecoder>,
+    pos: Option<PosReportAssembler>,
+}
 
-                    // Wait a bit before retrying
-                    std::thread::sleep(Duration::from_millis(100));
-                }
-            }
+impl BarcodeDecoder {
+    pub fn new(config: &Config, reader: &ReaderMatch) -> Self {
+        let framing = config.barcode_framing();
+        let pass_key = configured_key(config);
+        Self {
+            rules: ScanRules { formats: config.barcode_formats(), pass_key },
+            framer: BarcodeFramer::new(framing.clone()),
+            framing,
+            keyboard: (reader.report_format == ReportFormat::BootKeyboard)
+                .then(|| BootKeyboardDecoder::new(reader.keyboard_layout)),
+            pos: (reader.report_format == ReportFormat::HidPos).then(PosReportAssembler::default),
         }
-    });
+    }
 }
 
-pub fn open_symbol_scanner(api: &HidApi) -> Option<HidDevice> {
-    for device in api.device_list() {
-        let vendor_match = device.vendor_id() == 0x05e0;
-        let manufacturer = device.manufacturer_string().unwrap_or_default();
-        let product = device.product_string().unwrap_or_default();
+impl ReaderDecoder for BarcodeDecoder {
+    fn idle_timeout(&self) -> Duration {
+        self.framing.timeout()
+    }
 
-        let name_match = manufacturer.contains("Symbol")
-            || manufacturer.contains("Zebra")
-            || product.contains("Scanner");
+    fn frame(&mut self, data: &[u8], at: Instant, sink: &dyn EventSink) {
+        if let Some(assembler) = self.pos.as_mut() {
+            // POS reports carry the whole barcode, so no framing is needed
+            if let Some(scan) = assembler.push(data) {
+                let data = String::from_utf8_lossy(&scan.data).into_owned();
+                handle_barcode(sink, &self.rules, self.framing.check(&data).map(|()| data), scan.symbology);
+            }
+            return;
+        }
+        let part = match self.keyboard.as_mut() {
+            Some(decoder) => decoder.decode(data),
+            None => String::from_utf8_lossy(data).into_owned(),
+        };
+        // Key releases carry no characters and must not restart the timeout
+        if part.is_empty() {
+            return;
+        }
+        for framed in self.framer.push(&part, at) {
+            handle_barcode(sink, &self.rules, framed, Symbology::Unknown);
+        }
+    }
 
-        if vendor_match || name_match {
-            return api.open_path(device.path()).ok();
+    fn idle(&mut self, at: Instant, sink: &dyn EventSink) {
+        // The scanner went quiet, which ends a barcode sent without a terminator
+        if let Some(framed) = self.framer.flush_if_idle(at) {
+            handle_barcode(sink, &self.rules, framed, Symbology::Unknown);
         }
     }
-    None
 }
@@ -0,0 +1,327 @@
+use crate::devices::registry::ReaderMatch;
+use crate::devices::source::{InputSource, SourceHealth};
+use crate::error::GuestbookError;
+use chrono::{Local, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Longest pause kept on replay. Anything longer already ends a scan or
+/// swipe, so a capture left running all day replays in seconds.
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(1);
+
+/// First line of a capture file: the reader and the registry entry its
+/// frames were decoded with.
+#[derive(Debug, Serialize, Deserialize)]
+struct CaptureHeader {
+    reader: String,
+    entry: ReaderMatch,
+    started: String,
+}
+
+/// Every other line: one raw frame, with its time since the capture started.
+#[derive(Debug, Serialize, Deserialize)]
+struct CapturedFrame {
+    at_ms: u64,
+    /// Frame bytes in hex.
+    data: String,
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Captures live in a `captures/` directory next to the config file.
+pub fn capture_dir(config_path: &Path) -> PathBuf {
+    config_path.parent().unwrap().join("captures")
+}
+
+/// A capture file being written, with its header in place.
+pub struct Capture {
+    file: File,
+    path: PathBuf,
+    started: Instant,
+}
+
+/// Create a new file only the kiosk user can read, as captures hold
+/// unmasked card data. Never reuses an existing file.
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+impl Capture {
+    /// Start a new capture of `reader` in `dir`.
+    pub fn create(reader: &str, entry: &ReaderMatch, dir: &Path) -> Result<Self, GuestbookError> {
+        let storage = |e: std::io::Error| GuestbookError::Storage(e.to_string());
+        fs::create_dir_all(dir).map_err(storage)?;
+        let stem = format!("{}-{}", reader, Local::now().format("%Y%m%d-%H%M%S"));
+        // A reader reopened within the same second gets a numbered file
+        let mut path = dir.join(format!("{}.jsonl", stem));
+        let mut n = 0;
+        let mut file = loop {
+            match create_private(&path) {
+                Ok(file) => break file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    n += 1;
+                    path = dir.join(format!("{}-{}.jsonl", stem, n));
+                }
+                Err(e) => return Err(storage(e)),
+            }
+        };
+        let header = CaptureHeader {
+            reader: reader.to_string(),
+            entry: entry.clone(),
+            started: Utc::now().to_rfc3339(),
+        };
+        writeln!(file, "{}", serde_json::to_string(&header)?).map_err(storage)?;
+        info!("Recording {} input to {}", reader, path.display());
+        Ok(Self {
+            file,
+            path,
+            started: Instant::now(),
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        let frame = CapturedFrame {
+            at_ms: self.started.elapsed().as_millis() as u64,
+            data: to_hex(data),
+        };
+        let written = serde_json::to_string(&frame)
+            .map_err(|e| e.to_string())
+            .and_then(|line| writeln!(self.file, "{}", line).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            warn!("Failed to write to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Passes a source's frames through unchanged and appends each one to a
+/// capture file.
+pub struct RecordingSource {
+    inner: Box<dyn InputSource>,
+    capture: Capture,
+}
+
+impl RecordingSource {
+    pub fn new(inner: Box<dyn InputSource>, capture: Capture) -> Self {
+        Self { inner, capture }
+    }
+}
+
+impl InputSource for RecordingSource {
+    fn open(&mut self) -> Result<(), GuestbookError> {
+        self.inner.open()
+    }
+
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, GuestbookError> {
+        let frame = self.inner.read_frame(timeout)?;
+        if let Some(data) = &frame {
+            self.capture.write(data);
+        }
+        Ok(frame)
+    }
+
+    fn health(&self) -> SourceHealth {
+        self.inner.health()
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+}
+
+/// Plays a capture back at the pace it was recorded, so the frames reach
+/// the decoder with the same gaps between them. Fails once it has played
+/// everything.
+pub struct ReplaySource {
+    path: PathBuf,
+    /// Frames with their offset from the start of the replay.
+    frames: VecDeque<(Duration, Vec<u8>)>,
+    started: Instant,
+    /// Whether the pause after the last frame was played, so a scan or
+    /// swipe without a terminator still completes.
+    drained: bool,
+}
+
+/// Read a capture file, returning the entry it was recorded with and a
+/// source that replays it.
+pub fn load_capture(path: &Path) -> Result<(ReplaySource, ReaderMatch), GuestbookError> {
+    let parse = |line: usize, e: &dyn std::fmt::Display| {
+        GuestbookError::Parse(format!("{} line {}: {}", path.display(), line + 1, e))
+    };
+    let file = File::open(path).map_err(|e| GuestbookError::Storage(e.to_string()))?;
+    let mut lines = BufReader::new(file).lines().enumerate();
+    let header: CaptureHeader = match lines.next() {
+        Some((_, Ok(line))) => serde_json::from_str(&line).map_err(|e| parse(0, &e))?,
+        Some((i, Err(e))) => return Err(parse(i, &e)),
+        None => return Err(parse(0, &"empty capture")),
+    };
+    let mut frames = VecDeque::new();
+    let mut previous = 0;
+    let mut offset = Duration::ZERO;
+    for (i, line) in lines {
+        let line = line.map_err(|e| parse(i, &e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let frame: CapturedFrame = serde_json::from_str(&line).map_err(|e| parse(i, &e))?;
+        let data = from_hex(&frame.data).ok_or_else(|| parse(i, &"bad hex data"))?;
+        let gap = Duration::from_millis(frame.at_ms.saturating_sub(previous));
+        offset += gap.min(MAX_REPLAY_GAP);
+        previous = frame.at_ms;
+        frames.push_back((offset, data));
+    }
+    info!(
+        "Loaded {} frames of {} input from {}",
+        frames.len(),
+        header.reader,
+        path.display()
+    );
+    let source = ReplaySource {
+        path: path.to_path_buf(),
+        frames,
+        started: Instant::now(),
+        drained: false,
+    };
+    Ok((source, header.entry))
+}
+
+impl InputSource for ReplaySource {
+    fn open(&mut self) -> Result<(), GuestbookError> {
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, GuestbookError> {
+        let Some((offset, _)) = self.frames.front() else {
+            if self.drained {
+                return Err(GuestbookError::Hid(format!(
+                    "{}: end of capture",
+                    self.path.display()
+                )));
+            }
+            self.drained = true;
+            std::thread::sleep(timeout);
+            return Ok(None);
+        };
+        let wait = (self.started + *offset).saturating_duration_since(Instant::now());
+        if wait > timeout {
+            std::thread::sleep(timeout);
+            return Ok(None);
+        }
+        std::thread::sleep(wait);
+        Ok(self.frames.pop_front().map(|(_, data)| data))
+    }
+
+    fn health(&self) -> SourceHealth {
+        if self.drained && self.frames.is_empty() {
+            SourceHealth::Failed("end of capture".to_string())
+        } else {
+            SourceHealth::Ok
+        }
+    }
+
+    fn close(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config_manager::Config;
+    use crate::devices::registry::{default_registry, ReaderRole};
+    use crate::devices::test_support::{start_dispatcher, FakeSource};
+    use crate::devices::READERS;
+
+    /// Replay a capture through the decoder of its reader and collect the
+    /// events it emits.
+    fn replay(path: &Path) -> Vec<(String, serde_json::Value)> {
+        let (source, entry) = load_capture(path).unwrap();
+        let kind = READERS.iter().find(|kind| kind.role == entry.role).unwrap();
+        let (dispatcher, emitted, _) = start_dispatcher();
+        dispatcher
+            .attach(
+                kind.name,
+                entry.label(),
+                Box::new(source),
+                (kind.decoder)(&Config::default(), &entry),
+            )
+            .unwrap();
+        let mut events = Vec::new();
+        while let Ok((event, payload)) = emitted.recv_timeout(Duration::from_secs(5)) {
+            if event == "hid-error" {
+                break;
+            }
+            events.push((event, payload));
+        }
+        events
+    }
+
+    #[test]
+    fn recorded_swipes_replay_through_the_same_decoder() {
+        let dir = std::env::temp_dir().join(format!("guestbook-capture-{}", std::process::id()));
+        let entry = default_registry()
+            .into_iter()
+            .find(|entry| entry.role == ReaderRole::Swipe)
+            .unwrap();
+        // A swipe split across reports
+        let reader = FakeSource::new(vec![
+            Some(b"%B1234567   ^DOE/JOHN^"),
+            Some(b"1234567890123456?"),
+        ])
+        .with_delay(Duration::from_millis(20));
+        let capture = Capture::create("magtek", &entry, &dir).unwrap();
+        let path = capture.path.clone();
+        let mut recording = RecordingSource::new(Box::new(reader), capture);
+        while recording.read_frame(Duration::from_millis(150)).is_ok() {}
+        drop(recording);
+
+        let events = replay(&path);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, "magtek-data");
+        assert_eq!(events[0].1["onecard"], "1234567");
+        assert_eq!(events[0].1["name"], "DOE/JOHN");
+        fs::remove_dir_all(dir).ok();
+
+        assert_eq!(
+            from_hex(&to_hex(&[0x00, 0x7f, 0xff])),
+            Some(vec![0x00, 0x7f, 0xff])
+        );
+        assert_eq!(from_hex("0g"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn captures_are_new_files_only_the_owner_can_read() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("guestbook-private-{}", std::process::id()));
+        let entry = default_registry().remove(0);
+        let first = Capture::create("magtek", &entry, &dir).unwrap();
+        let second = Capture::create("magtek", &entry, &dir).unwrap();
+        assert_ne!(first.path, second.path);
+        for capture in [&first, &second] {
+            let mode = fs::metadata(&capture.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,477 @@
+use crate::devices::dispatcher::{EventSink, ReaderDecoder};
+use crate::devices::normalize::BarcodeFormat;
+use crate::devices::registry::ReaderMatch;
+use crate::devices::source::{InputSource, SourceHealth};
+use crate::error::GuestbookError;
+use log::{info, warn};
+use pcsc::{Context, Disposition, Protocols, ReaderState, Scope, ShareMode, State};
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::time::{Duration, Instant};
+
+/// How long the source waits for the reader's state to change before
+/// reporting it is idle.
+const STATUS_TIMEOUT: Duration = Duration::from_millis(500);
+/// PC/SC pseudo-APDU asking the reader for the UID of the card on it.
+const GET_UID: [u8; 5] = [0xff, 0xca, 0x00, 0x00, 0x00];
+const SW_OK: [u8; 2] = [0x90, 0x00];
+/// DESFire native commands, sent wrapped in ISO 7816 APDUs.
+const DESFIRE_SELECT_APPLICATION: u8 = 0x5a;
+const DESFIRE_READ_DATA: u8 = 0xbd;
+const DESFIRE_ADDITIONAL_FRAME: u8 = 0xaf;
+const DESFIRE_OK: [u8; 2] = [0x91, 0x00];
+const DESFIRE_MORE: [u8; 2] = [0x91, 0xaf];
+
+/// A DESFire standard data file holding the OneCard ID as text, readable
+/// without authentication.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct IdFile {
+    /// Application ID in hex as written in the card layout, e.g. "F51230".
+    pub application: String,
+    pub file: u8,
+    #[serde(default)]
+    pub offset: u32,
+    /// Bytes to read; the rest of the file when 0.
+    #[serde(default)]
+    pub length: u32,
+    /// Layout of the ID in the file; the bare OneCard number when unset.
+    #[serde(default)]
+    pub format: Option<BarcodeFormat>,
+}
+
+impl IdFile {
+    /// The application ID as sent to the card, least significant byte first.
+    fn application_id(&self) -> Result<[u8; 3], GuestbookError> {
+        match u32::from_str_radix(self.application.trim(), 16) {
+            Ok(aid) if aid <= 0xff_ffff => Ok(le24(aid)),
+            _ => Err(GuestbookError::Config(format!(
+                "invalid DESFire application ID {:?}",
+                self.application
+            ))),
+        }
+    }
+}
+
+fn le24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.to_le_bytes();
+    [a, b, c]
+}
+
+/// Payload of the `contactless-data` event.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ContactlessCard {
+    pub onecard: String,
+    /// Card UID in hex.
+    pub uid: String,
+}
+
+/// One tap, passed from `PcscSource` to `TapDecoder` as a JSON frame.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Tap {
+    uid: String,
+    /// Contents of the ID file, if one is configured and could be read.
+    id_file: Option<Vec<u8>>,
+}
+
+/// The frame for a tap of the card with `uid`, as if read by `PcscSource`.
+pub fn uid_frame(uid: &str, id_file: Option<&[u8]>) -> Vec<u8> {
+    let tap = Tap {
+        uid: uid.trim().to_uppercase(),
+        id_file: id_file.map(<[u8]>::to_vec),
+    };
+    serde_json::to_vec(&tap).unwrap_or_default()
+}
+
+/// Sends APDUs to a card: one on a PC/SC reader, or a fake one in tests.
+trait Apdu {
+    fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>, GuestbookError>;
+}
+
+impl Apdu for pcsc::Card {
+    fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>, GuestbookError> {
+        let mut buffer = [0u8; pcsc::MAX_BUFFER_SIZE];
+        Ok(pcsc::Card::transmit(self, command, &mut buffer)?.to_vec())
+    }
+}
+
+/// Split a response into its data and status word.
+fn split_status(response: &[u8]) -> Result<(&[u8], [u8; 2]), GuestbookError> {
+    match response {
+        [data @ .., sw1, sw2] => Ok((data, [*sw1, *sw2])),
+        _ => Err(GuestbookError::Hid("card sent no status word".to_string())),
+    }
+}
+
+fn desfire_command(command: u8, data: &[u8]) -> Vec<u8> {
+    let mut apdu = vec![0x90, command, 0x00, 0x00];
+    if !data.is_empty() {
+        apdu.push(data.len() as u8);
+        apdu.extend_from_slice(data);
+    }
+    apdu.push(0x00);
+    apdu
+}
+
+fn desfire_error(status: [u8; 2]) -> GuestbookError {
+    let reason = match status {
+        [0x91, 0x9d] => "permission denied",
+        [0x91, 0xa0] => "application not found",
+        [0x91, 0xbe] => "read past the end of the file",
+        [0x91, 0xf0] => "file not found",
+        _ => "command failed",
+    };
+    GuestbookError::Hid(format!(
+        "DESFire status {:02X}{:02X}: {}",
+        status[0], status[1], reason
+    ))
+}
+
+fn read_uid(card: &mut impl Apdu) -> Result<String, GuestbookError> {
+    let response = card.transmit(&GET_UID)?;
+    match split_status(&response)? {
+        (uid, SW_OK) if !uid.is_empty() => Ok(uid.iter().map(|b| format!("{:02X}", b)).collect()),
+        (_, [sw1, sw2]) => Err(GuestbookError::Hid(format!(
+            "reader returned no card UID (status {:02X}{:02X})",
+            sw1, sw2
+        ))),
+    }
+}
+
+fn read_id_file(card: &mut impl Apdu, file: &IdFile) -> Result<Vec<u8>, GuestbookError> {
+    let response = card.transmit(&desfire_command(
+        DESFIRE_SELECT_APPLICATION,
+        &file.application_id()?,
+    ))?;
+    match split_status(&response)? {
+        (_, DESFIRE_OK) => {}
+        (_, status) => return Err(desfire_error(status)),
+    }
+    let mut read = vec![file.file];
+    read.extend(le24(file.offset));
+    read.extend(le24(file.length));
+    let mut command = desfire_command(DESFIRE_READ_DATA, &read);
+    let mut data = Vec::new();
+    // Files longer than a frame come in parts, each asked for in turn
+    loop {
+        let response = card.transmit(&command)?;
+        let (part, status) = split_status(&response)?;
+        data.extend_from_slice(part);
+        match status {
+            DESFIRE_OK => return Ok(data),
+            DESFIRE_MORE => command = desfire_command(DESFIRE_ADDITIONAL_FRAME, &[]),
+            status => return Err(desfire_error(status)),
+        }
+    }
+}
+
+fn read_tap(card: &mut impl Apdu, id_file: Option<&IdFile>) -> Result<Tap, GuestbookError> {
+    let uid = read_uid(card)?;
+    let id_file = id_file.and_then(|file| {
+        read_id_file(card, file)
+            .inspect_err(|e| warn!("Could not read the ID file of card {}: {}", uid, e))
+            .ok()
+    });
+    Ok(Tap { uid, id_file })
+}
+
+/// Names of the attached PC/SC readers.
+pub fn available_readers() -> Vec<CString> {
+    let listed = Context::establish(Scope::User).and_then(|context| context.list_readers_owned());
+    match listed {
+        Ok(readers) => readers,
+        // Kiosks without a contactless reader needn't run the PC/SC service
+        Err(pcsc::Error::NoService | pcsc::Error::NoReadersAvailable) => Vec::new(),
+        Err(e) => {
+            warn!("Failed to list PC/SC readers: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// The first PC/SC reader whose name starts with the entry's `pcsc_reader`.
+pub fn locate_pcsc_reader(entry: &ReaderMatch, readers: &[CString]) -> Option<CString> {
+    readers
+        .iter()
+        .find(|name| {
+            entry
+                .pcsc_reader
+                .as_deref()
+                .is_none_or(|prefix| name.to_string_lossy().starts_with(prefix))
+        })
+        .cloned()
+}
+
+/// A contactless reader used through PC/SC, e.g. an ACR122U. Each card
+/// placed on the reader is one frame.
+pub struct PcscSource {
+    reader: CString,
+    id_file: Option<IdFile>,
+    context: Option<Context>,
+    /// Reader state after the last status change.
+    state: State,
+    error: Option<String>,
+}
+
+impl PcscSource {
+    pub fn new(reader: CString, id_file: Option<IdFile>) -> Self {
+        Self {
+            reader,
+            id_file,
+            context: None,
+            state: State::UNAWARE,
+            error: None,
+        }
+    }
+
+    fn fail(&mut self, e: impl std::fmt::Display) -> GuestbookError {
+        let message = format!("{}: {}", self.reader.to_string_lossy(), e);
+        self.error = Some(message.clone());
+        GuestbookError::Hid(message)
+    }
+}
+
+impl InputSource for PcscSource {
+    fn open(&mut self) -> Result<(), GuestbookError> {
+        let context = Context::establish(Scope::User).map_err(|e| self.fail(e))?;
+        self.context = Some(context);
+        self.state = State::UNAWARE;
+        self.error = None;
+        Ok(())
+    }
+
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, GuestbookError> {
+        let context = self
+            .context
+            .as_ref()
+            .ok_or_else(|| GuestbookError::Hid("reader is not open".to_string()))?;
+        let mut readers = [ReaderState::new(self.reader.clone(), self.state)];
+        match context.get_status_change(timeout, &mut readers) {
+            Ok(()) => {}
+            Err(pcsc::Error::Timeout) => return Ok(None),
+            Err(e) => return Err(self.fail(e)),
+        }
+        let before = self.state;
+        let now = readers[0].event_state();
+        readers[0].sync_current_state();
+        self.state = readers[0].current_state();
+        if now.intersects(State::UNKNOWN | State::UNAVAILABLE) {
+            return Err(self.fail("reader was removed"));
+        }
+        // Only a card arriving is a tap, not one left on the reader or
+        // already there when the app started
+        if !(before.contains(State::EMPTY) && now.contains(State::PRESENT))
+            || now.contains(State::MUTE)
+        {
+            return Ok(None);
+        }
+        let tap = context
+            .connect(&self.reader, ShareMode::Shared, Protocols::ANY)
+            .map_err(GuestbookError::from)
+            .and_then(|mut card| {
+                let tap = read_tap(&mut card, self.id_file.as_ref());
+                card.disconnect(Disposition::LeaveCard).ok();
+                tap
+            });
+        match tap {
+            Ok(tap) => Ok(Some(serde_json::to_vec(&tap)?)),
+            // Usually the card was taken away too soon
+            Err(e) => {
+                warn!("Could not read contactless card: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn health(&self) -> SourceHealth {
+        match (&self.context, &self.error) {
+            (_, Some(e)) => SourceHealth::Failed(e.clone()),
+            (None, None) => SourceHealth::Failed("reader is not open".to_string()),
+            (Some(_), None) => SourceHealth::Ok,
+        }
+    }
+
+    fn close(&mut self) {
+        self.context = None;
+    }
+}
+
+/// Turns taps into `contactless-data` events, with the OneCard ID from the
+/// card's ID file. A UID is not a OneCard ID, so taps on a reader without an
+/// `id_file` are only passed on as `hid-data`.
+pub struct TapDecoder {
+    id_format: Option<BarcodeFormat>,
+}
+
+impl TapDecoder {
+    pub fn new(reader: &ReaderMatch) -> Self {
+        Self {
+            id_format: reader.id_file.as_ref().map(|file| {
+                file.format.clone().unwrap_or_else(|| BarcodeFormat {
+                    name: "onecard".to_string(),
+                    length: None,
+                    steps: Vec::new(),
+                })
+            }),
+        }
+    }
+}
+
+impl ReaderDecoder for TapDecoder {
+    fn idle_timeout(&self) -> Duration {
+        STATUS_TIMEOUT
+    }
+
+    fn frame(&mut self, data: &[u8], _at: Instant, sink: &dyn EventSink) {
+        let tap: Tap = match serde_json::from_slice(data) {
+            Ok(tap) => tap,
+            Err(e) => {
+                warn!("Ignoring contactless frame: {}", e);
+                return;
+            }
+        };
+        let Some(format) = &self.id_format else {
+            warn!(
+                "Not submitting contactless card {}: the reader has no id_file",
+                tap.uid
+            );
+            sink.emit("hid-data", tap.uid);
+            return;
+        };
+        let onecard = tap.id_file.as_ref().and_then(|file| {
+            let text = String::from_utf8_lossy(file);
+            format.normalize(text.trim_matches(|c: char| c == '\0' || c.is_whitespace()))
+        });
+        match onecard {
+            Some(onecard) => {
+                info!("Contactless card tapped: {} (UID {})", onecard, tap.uid);
+                let card = ContactlessCard {
+                    onecard,
+                    uid: tap.uid,
+                };
+                sink.emit("contactless-data", card);
+            }
+            None => {
+                warn!("No OneCard ID found on contactless card {}", tap.uid);
+                sink.emit("hid-data", tap.uid);
+            }
+        }
+    }
+
+    fn idle(&mut self, _at: Instant, _sink: &dyn EventSink) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::registry::ReaderMatch;
+    use std::collections::VecDeque;
+    use std::sync::mpsc::channel;
+
+    /// Answers each APDU with the next scripted response, checking the
+    /// command it was sent.
+    struct FakeCard {
+        script: VecDeque<(Vec<u8>, Vec<u8>)>,
+    }
+
+    impl Apdu for FakeCard {
+        fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>, GuestbookError> {
+            let (expected, response) = self.script.pop_front().expect("unexpected APDU");
+            assert_eq!(command, expected.as_slice());
+            Ok(response)
+        }
+    }
+
+    fn pcsc_entry() -> ReaderMatch {
+        serde_json::from_str(r#"{ "role": "contactless", "source": "pcsc" }"#).unwrap()
+    }
+
+    #[test]
+    fn reads_the_uid_and_a_multi_frame_id_file() {
+        let mut reader = pcsc_entry();
+        reader.id_file = Some(
+            serde_json::from_str(r#"{ "application": "F51230", "file": 1, "length": 9 }"#).unwrap(),
+        );
+        let mut card = FakeCard {
+            script: VecDeque::from([
+                (
+                    GET_UID.to_vec(),
+                    vec![0x04, 0xa2, 0x2b, 0x91, 0x00, 0x90, 0x00],
+                ),
+                (
+                    vec![0x90, 0x5a, 0x00, 0x00, 0x03, 0x30, 0x12, 0xf5, 0x00],
+                    vec![0x91, 0x00],
+                ),
+                (
+                    vec![
+                        0x90, 0xbd, 0x00, 0x00, 0x07, 0x01, 0, 0, 0, 0x09, 0, 0, 0x00,
+                    ],
+                    [b"00123".as_slice(), &[0x91, 0xaf]].concat(),
+                ),
+                (
+                    vec![0x90, 0xaf, 0x00, 0x00, 0x00],
+                    [b"4567".as_slice(), &[0x91, 0x00]].concat(),
+                ),
+            ]),
+        };
+        let tap = read_tap(&mut card, reader.id_file.as_ref()).unwrap();
+        assert_eq!(tap.uid, "04A22B9100");
+        assert_eq!(tap.id_file.as_deref(), Some(b"001234567".as_slice()));
+
+        let (sink, emitted) = channel::<(String, serde_json::Value)>();
+        let sink: &dyn EventSink = &sink;
+        let frame = |tap: &Tap| serde_json::to_vec(tap).unwrap();
+        // Nine digits don't fit the bare OneCard format
+        TapDecoder::new(&reader).frame(&frame(&tap), Instant::now(), sink);
+        assert_eq!(emitted.try_recv().unwrap().0, "hid-data");
+
+        reader.id_file.as_mut().unwrap().format = Some(
+            serde_json::from_str(
+                r#"{ "name": "desfire", "steps": [{ "kind": "drop_leading", "count": 2 }] }"#,
+            )
+            .unwrap(),
+        );
+        TapDecoder::new(&reader).frame(&frame(&tap), Instant::now(), sink);
+        let (event, payload) = emitted.try_recv().unwrap();
+        assert_eq!(event, "contactless-data");
+        assert_eq!(payload["onecard"], "1234567");
+        assert_eq!(payload["uid"], "04A22B9100");
+
+        // Without an ID file there is no OneCard ID to submit
+        reader.id_file = None;
+        TapDecoder::new(&reader).frame(&frame(&tap), Instant::now(), sink);
+        assert_eq!(
+            emitted.try_recv().unwrap(),
+            ("hid-data".to_string(), "04A22B9100".into())
+        );
+    }
+
+    #[test]
+    fn reports_missing_applications_and_matches_reader_names() {
+        let file: IdFile =
+            serde_json::from_str(r#"{ "application": "F51230", "file": 1 }"#).unwrap();
+        let mut card = FakeCard {
+            script: VecDeque::from([(
+                desfire_command(DESFIRE_SELECT_APPLICATION, &[0x30, 0x12, 0xf5]),
+                vec![0x91, 0xa0],
+            )]),
+        };
+        let error = read_id_file(&mut card, &file).unwrap_err();
+        assert!(error.to_string().contains("application not found"));
+
+        let mut entry = pcsc_entry();
+        let readers = [
+            CString::new("Virtual PCD 00 00").unwrap(),
+            CString::new("ACS ACR122U PICC Interface 01 00").unwrap(),
+        ];
+        assert_eq!(
+            locate_pcsc_reader(&entry, &readers),
+            Some(readers[0].clone())
+        );
+        entry.pcsc_reader = Some("ACS ACR122U".to_string());
+        assert_eq!(
+            locate_pcsc_reader(&entry, &readers),
+            Some(readers[1].clone())
+        );
+        assert_eq!(locate_pcsc_reader(&entry, &readers[..1]), None);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::devices::source::{InputSource, SourceHealth};
+use crate::devices::{Listeners, LISTENERS};
+use crate::error::GuestbookError;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+/// Pause after a failed read before trying again.
+const READ_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Where decoded input goes: the frontend in the app, a channel in tests.
+pub trait EventSink: Send {
+    fn send(&self, event: &str, payload: serde_json::Value);
+}
+
+impl EventSink for AppHandle {
+    fn send(&self, event: &str, payload: serde_json::Value) {
+        self.emit(event, payload).ok();
+    }
+}
+
+impl dyn EventSink + '_ {
+    pub fn emit<T: Serialize>(&self, event: &str, payload: T) {
+        match serde_json::to_value(payload) {
+            Ok(payload) => self.send(event, payload),
+            Err(e) => error!("Failed to serialize {} event: {}", event, e),
+        }
+    }
+}
+
+/// Turns a reader's frames into frontend events, e.g. barcodes or swipes.
+pub trait ReaderDecoder: Send {
+    /// How long the source waits for a frame before reporting it is idle.
+    fn idle_timeout(&self) -> Duration;
+
+    fn frame(&mut self, data: &[u8], at: Instant, sink: &dyn EventSink);
+
+    /// Nothing arrived within `idle_timeout`.
+    fn idle(&mut self, at: Instant, sink: &dyn EventSink);
+}
+
+enum SourceEvent {
+    Frame { data: Vec<u8>, at: Instant },
+    Idle(Instant),
+    Failed(String),
+}
+
+enum InputEvent {
+    Attached {
+        reader: &'static str,
+        generation: u64,
+        label: String,
+        decoder: Box<dyn ReaderDecoder>,
+    },
+    Source {
+        reader: &'static str,
+        generation: u64,
+        event: SourceEvent,
+    },
+}
+
+struct AttachedReader {
+    generation: u64,
+    label: String,
+    decoder: Box<dyn ReaderDecoder>,
+}
+
+/// Receives the frames of every reader on one thread and hands them to the
+/// reader's decoder, so sources only have to produce bytes.
+pub struct Dispatcher {
+    events: Sender<InputEvent>,
+    listeners: Arc<Listeners>,
+}
+
+impl Dispatcher {
+    pub fn start(sink: impl EventSink + 'static) -> Self {
+        Self::with_listeners(sink, LISTENERS.clone())
+    }
+
+    /// A dispatcher that records listener states in `listeners` instead of
+    /// the app-wide `LISTENERS`.
+    pub fn with_listeners(sink: impl EventSink + 'static, listeners: Arc<Listeners>) -> Self {
+        let (events, received) = channel();
+        let dispatch_listeners = listeners.clone();
+        std::thread::spawn(move || dispatch(received, sink, &dispatch_listeners));
+        Self { events, listeners }
+    }
+
+    /// Open `source` and start reading it on its own thread, replacing any
+    /// earlier source of the same reader.
+    pub fn attach(
+        &self,
+        reader: &'static str,
+        label: String,
+        mut source: Box<dyn InputSource>,
+        decoder: Box<dyn ReaderDecoder>,
+    ) -> Result<(), GuestbookError> {
+        source.open()?;
+        info!("Starting {} listener", label);
+        let generation = self.listeners.started(reader);
+        let idle_timeout = decoder.idle_timeout();
+        self.events
+            .send(InputEvent::Attached {
+                reader,
+                generation,
+                label,
+                decoder,
+            })
+            .map_err(|_| GuestbookError::Hid("input dispatcher is not running".to_string()))?;
+        let events = self.events.clone();
+        let listeners = self.listeners.clone();
+        std::thread::spawn(move || {
+            read_source(reader, generation, source, idle_timeout, events, &listeners)
+        });
+        Ok(())
+    }
+}
+
+fn read_source(
+    reader: &'static str,
+    generation: u64,
+    mut source: Box<dyn InputSource>,
+    idle_timeout: Duration,
+    events: Sender<InputEvent>,
+    listeners: &Listeners,
+) {
+    // A replugged reader gets a new source; the old one stops here
+    while listeners.is_current(reader, generation) {
+        let event = match source.read_frame(idle_timeout) {
+            Ok(Some(data)) => SourceEvent::Frame {
+                data,
+                at: Instant::now(),
+            },
+            Ok(None) => SourceEvent::Idle(Instant::now()),
+            Err(e) => match source.health() {
+                SourceHealth::Failed(reason) => SourceEvent::Failed(reason),
+                _ => {
+                    warn!("{} read error: {}", reader, e);
+                    std::thread::sleep(READ_RETRY_DELAY);
+                    continue;
+                }
+            },
+        };
+        let failed = matches!(event, SourceEvent::Failed(_));
+        let event = InputEvent::Source {
+            reader,
+            generation,
+            event,
+        };
+        if events.send(event).is_err() || failed {
+            break;
+        }
+    }
+    source.close();
+}
+
+fn dispatch(events: Receiver<InputEvent>, sink: impl EventSink, listeners: &Listeners) {
+    let sink: &dyn EventSink = &sink;
+    let mut readers: HashMap<&'static str, AttachedReader> = HashMap::new();
+    for event in events {
+        let (reader, generation, event) = match event {
+            InputEvent::Attached {
+                reader,
+                generation,
+                label,
+                decoder,
+            } => {
+                let attached = AttachedReader {
+                    generation,
+                    label,
+                    decoder,
+                };
+                readers.insert(reader, attached);
+                continue;
+            }
+            InputEvent::Source {
+                reader,
+                generation,
+                event,
+            } => (reader, generation, event),
+        };
+        // Frames still in flight from a source that was replaced
+        let Some(attached) = readers
+            .get_mut(reader)
+            .filter(|attached| attached.generation == generation)
+        else {
+            continue;
+        };
+        match event {
+            SourceEvent::Frame { data, at } => attached.decoder.frame(&data, at, sink),
+            SourceEvent::Idle(at) => attached.decoder.idle(at, sink),
+            SourceEvent::Failed(reason) => {
+                error!("{} failed, stopping listener: {}", attached.label, reason);
+                if listeners.stopped(reader, generation, reason.clone()) {
+                    let error =
+                        GuestbookError::Hid(format!("{} failed: {}", attached.label, reason));
+                    sink.emit("hid-error", error);
+                }
+                readers.remove(reader);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config_manager::Config;
+    use crate::devices::barcode::BarcodeDecoder;
+    use crate::devices::magtek::SwipeDecoder;
+    use crate::devices::registry::{default_registry, ReaderRole};
+    use crate::devices::test_support::{start_dispatcher, FakeSource};
+    use crate::devices::ReaderState;
+
+    #[test]
+    fn decodes_fake_sources_without_hardware_or_a_window() {
+        let (dispatcher, emitted, listeners) = start_dispatcher();
+        let config = Config::default();
+        let next = || emitted.recv_timeout(Duration::from_secs(5)).unwrap();
+        let reader = |role| {
+            default_registry()
+                .into_iter()
+                .find(|entry| entry.role == role)
+                .unwrap()
+        };
+
+        dispatcher
+            .attach(
+                "barcode",
+                "Fake scanner".to_string(),
+                FakeSource::boxed(vec![Some(b"12345"), Some(b"67\r")]),
+                Box::new(BarcodeDecoder::new(&config, &reader(ReaderRole::Barcode))),
+            )
+            .unwrap();
+        let (event, payload) = next();
+        assert_eq!(event, "barcode-data");
+        assert_eq!(payload["value"], "1234567");
+        let (event, payload) = next();
+        assert_eq!(event, "hid-error");
+        assert!(payload["message"]
+            .as_str()
+            .unwrap()
+            .contains("Fake scanner failed: unplugged"));
+        assert_eq!(
+            listeners.states().get("barcode"),
+            Some(&ReaderState::Stopped("unplugged".to_string()))
+        );
+
+        // The swipe is complete once the reader goes quiet
+        dispatcher
+            .attach(
+                "magtek",
+                "Fake swipe reader".to_string(),
+                FakeSource::boxed(vec![
+                    Some(b"%B1234567   ^DOE/JOHN^"),
+                    Some(b"1234567890123456?"),
+                    None,
+                ]),
+                Box::new(SwipeDecoder::new(&config, &reader(ReaderRole::Swipe))),
+            )
+            .unwrap();
+        let (event, payload) = next();
+        assert_eq!(event, "magtek-data");
+        assert_eq!(payload["onecard"], "1234567");
+        assert_eq!(payload["name"], "DOE/JOHN");
+        assert_eq!(next().0, "hid-error");
+    }
+
+    #[test]
+    fn a_replaced_source_stops_without_reporting() {
+        let (dispatcher, emitted, listeners) = start_dispatcher();
+        let config = Config::default();
+        let barcode = default_registry()
+            .into_iter()
+            .find(|entry| entry.role == ReaderRole::Barcode)
+            .unwrap();
+        let attach = |source: FakeSource| {
+            dispatcher
+                .attach(
+                    "barcode",
+                    "Fake scanner".to_string(),
+                    Box::new(source),
+                    Box::new(BarcodeDecoder::new(&config, &barcode)),
+                )
+                .unwrap()
+        };
+
+        // Would fail after half a second if it weren't replaced first
+        attach(FakeSource::new(vec![None; 50]).with_delay(Duration::from_millis(10)));
+        attach(FakeSource::new(vec![Some(b"7654321\r"), None]));
+        let next = || emitted.recv_timeout(Duration::from_secs(5)).unwrap();
+        let (event, payload) = next();
+        assert_eq!(event, "barcode-data");
+        assert_eq!(payload["value"], "7654321");
+        assert_eq!(next().0, "hid-error");
+        assert!(emitted.recv_timeout(Duration::from_secs(1)).is_err());
+        assert_eq!(
+            listeners.states().get("barcode"),
+            Some(&ReaderState::Stopped("unplugged".to_string()))
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use crate::devices::registry::ReaderMatch;
+use crate::devices::source::{InputSource, SourceHealth};
+use crate::error::GuestbookError;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// `_IOW('E', 0x90, int)`: take (1) or release (0) exclusive access to an
+/// input device, so its events reach no other reader such as the desktop.
+const EVIOCGRAB: u64 = 0x4004_4590;
+const EV_KEY: u16 = 0x01;
+/// Key codes of the modifiers, in the bit order of the boot-keyboard
+/// modifier byte: left Ctrl, Shift, Alt, Meta, then the right ones.
+const MODIFIER_KEYS: [u16; 8] = [29, 42, 56, 125, 97, 54, 100, 126];
+/// Key slots in a boot-keyboard report.
+const REPORT_KEYS: usize = 6;
+
+fn letter(c: u8) -> u8 {
+    0x04 + (c - b'a')
+}
+
+/// HID usage of a Linux key code, for the keys a scanner types.
+fn usage_for(code: u16) -> Option<u8> {
+    let i = usize::from(code);
+    Some(match code {
+        1 => 0x29,                         // Esc
+        2..=10 => 0x1e + (code - 2) as u8, // 1-9
+        11 => 0x27,
+        12 => 0x2d,
+        13 => 0x2e,
+        14 => 0x2a, // Backspace
+        15 => 0x2b, // Tab
+        16..=25 => letter(b"qwertyuiop"[i - 16]),
+        26 => 0x2f,
+        27 => 0x30,
+        28 => 0x28, // Enter
+        30..=38 => letter(b"asdfghjkl"[i - 30]),
+        39 => 0x33,
+        40 => 0x34,
+        41 => 0x35,
+        // The kernel reports the non-US # key as backslash too
+        43 => 0x31,
+        44..=50 => letter(b"zxcvbnm"[i - 44]),
+        51 => 0x36,
+        52 => 0x37,
+        53 => 0x38,
+        55 => 0x55,
+        57 => 0x2c,                          // Space
+        71..=73 => 0x5f + (code - 71) as u8, // Keypad 7-9
+        74 => 0x56,
+        75..=77 => 0x5c + (code - 75) as u8, // Keypad 4-6
+        78 => 0x57,
+        79..=81 => 0x59 + (code - 79) as u8, // Keypad 1-3
+        82 => 0x62,
+        83 => 0x63,
+        86 => 0x64, // Non-US backslash
+        96 => 0x58, // Keypad Enter
+        98 => 0x54,
+        _ => return None,
+    })
+}
+
+/// Turns key events into boot-keyboard reports, so keyboard-wedge scanners
+/// read through evdev share `BootKeyboardDecoder` and its layouts.
+#[derive(Default)]
+struct KeyReports {
+    modifiers: u8,
+    held: Vec<u8>,
+}
+
+impl KeyReports {
+    /// Report after a key event, or `None` if the event changes nothing.
+    fn key(&mut self, code: u16, value: i32) -> Option<Vec<u8>> {
+        // Value 2 is autorepeat, which must not count as another press
+        let pressed = match value {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        if let Some(bit) = MODIFIER_KEYS.iter().position(|&key| key == code) {
+            if pressed {
+                self.modifiers |= 1 << bit;
+            } else {
+                self.modifiers &= !(1 << bit);
+            }
+        } else {
+            let usage = usage_for(code)?;
+            self.held.retain(|&held| held != usage);
+            if pressed {
+                self.held.push(usage);
+            }
+        }
+        let mut report = vec![self.modifiers, 0];
+        report.extend(self.held.iter().take(REPORT_KEYS));
+        report.resize(2 + REPORT_KEYS, 0);
+        Some(report)
+    }
+}
+
+/// Event node for a reader: its `device_path`, if present. Entries without
+/// one match nothing, rather than grabbing whatever input device happens to
+/// share a vendor ID, such as the kiosk's own keyboard.
+pub fn locate_event_node(entry: &ReaderMatch) -> Option<PathBuf> {
+    let path = Path::new(entry.device_path.as_deref()?);
+    path.exists().then(|| path.to_path_buf())
+}
+
+/// A keyboard-wedge reader read from its `/dev/input/event*` node under an
+/// exclusive grab. Each key event becomes a boot-keyboard report frame.
+pub struct EvdevSource {
+    path: PathBuf,
+    file: Option<File>,
+    keys: KeyReports,
+    reports: VecDeque<Vec<u8>>,
+    error: Option<String>,
+}
+
+impl EvdevSource {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: None,
+            keys: KeyReports::default(),
+            reports: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    fn fail(&mut self, e: io::Error) -> GuestbookError {
+        let message = format!("{}: {}", self.path.display(), e);
+        self.error = Some(message.clone());
+        GuestbookError::Hid(message)
+    }
+}
+
+fn grab(file: &File, grab: bool) -> io::Result<()> {
+    // SAFETY: EVIOCGRAB takes an int argument and the fd is open
+    match unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB as _, libc::c_int::from(grab)) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Wait for the file to become readable. Returns false on timeout.
+fn wait_readable(file: &File, timeout: Duration) -> io::Result<bool> {
+    let mut fds = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout_ms = timeout.as_millis().try_into().unwrap_or(libc::c_int::MAX);
+    // SAFETY: fds is a single valid pollfd for the duration of the call
+    match unsafe { libc::poll(&mut fds, 1, timeout_ms) } {
+        0 => Ok(false),
+        n if n > 0 => Ok(true),
+        _ => match io::Error::last_os_error() {
+            e if e.kind() == io::ErrorKind::Interrupted => Ok(false),
+            e => Err(e),
+        },
+    }
+}
+
+impl InputSource for EvdevSource {
+    fn open(&mut self) -> Result<(), GuestbookError> {
+        let file = File::open(&self.path).map_err(|e| self.fail(e))?;
+        // Without the grab every scan would also be typed into the webview
+        grab(&file, true).map_err(|e| self.fail(e))?;
+        self.file = Some(file);
+        self.keys = KeyReports::default();
+        self.error = None;
+        Ok(())
+    }
+
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, GuestbookError> {
+        let deadline = Instant::now() + timeout;
+        let event_size = std::mem::size_of::<libc::input_event>();
+        let mut buffer = vec![0u8; event_size * 64];
+        // Sync and scancode events don't count as input, so keep waiting for a key
+        while self.reports.is_empty() {
+            let file = self
+                .file
+                .as_mut()
+                .ok_or_else(|| GuestbookError::Hid("device is not open".to_string()))?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let read = match wait_readable(file, remaining) {
+                Ok(false) => return Ok(None),
+                Ok(true) => file.read(&mut buffer),
+                Err(e) => Err(e),
+            };
+            let size = read.map_err(|e| self.fail(e))?;
+            for chunk in buffer[..size].chunks_exact(event_size) {
+                // SAFETY: the chunk is exactly one input_event, which is plain data
+                let event: libc::input_event =
+                    unsafe { std::ptr::read_unaligned(chunk.as_ptr().cast()) };
+                if event.type_ == EV_KEY {
+                    self.reports.extend(self.keys.key(event.code, event.value));
+                }
+            }
+        }
+        Ok(self.reports.pop_front())
+    }
+
+    fn health(&self) -> SourceHealth {
+        match (&self.file, &self.error) {
+            // A read error means the node is gone, e.g. the scanner was unplugged
+            (_, Some(e)) => SourceHealth::Failed(e.clone()),
+            (None, None) => SourceHealth::Failed("device is not open".to_string()),
+            (Some(_), None) => SourceHealth::Ok,
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(file) = self.file.take() {
+            grab(&file, false).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::keyboard::{BootKeyboardDecoder, KeyboardLayout};
+
+    #[test]
+    fn key_events_type_through_the_boot_keyboard_decoder() {
+        let mut keys = KeyReports::default();
+        let mut decoder = BootKeyboardDecoder::new(KeyboardLayout::Us);
+        let events = [
+            (42, 1), // Left Shift
+            (30, 1), // A
+            (30, 2), // autorepeat
+            (30, 0),
+            (42, 0),
+            (2, 1), // 1
+            (2, 0),
+            (2, 1),
+            (2, 0),
+            (240, 1), // Unknown key
+            (28, 1),  // Enter
+            (28, 0),
+        ];
+        let typed: String = events
+            .iter()
+            .filter_map(|&(code, value)| keys.key(code, value))
+            .map(|report| decoder.decode(&report))
+            .collect();
+        assert_eq!(typed, "A11\r");
+        assert_eq!(usage_for(16), Some(0x14)); // Q
+        assert_eq!(usage_for(50), Some(0x10)); // M
+        assert_eq!(usage_for(82), Some(0x62)); // Keypad 0
+    }
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Terminator {
+    Cr,
+    Lf,
+    Tab,
+}
+
+impl Terminator {
+    fn char(self) -> char {
+        match self {
+            Terminator::Cr => '\r',
+            Terminator::Lf => '\n',
+            Terminator::Tab => '\t',
+        }
+    }
+
+    pub fn byte(self) -> u8 {
+        self.char() as u8
+    }
+}
+
+/// Characters a barcode may contain.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CharSet {
+    Digits,
+    Alphanumeric,
+    /// Printable ASCII, including spaces.
+    Printable,
+}
+
+impl CharSet {
+    fn allows(self, c: char) -> bool {
+        match self {
+            CharSet::Digits => c.is_ascii_digit(),
+            CharSet::Alphanumeric => c.is_ascii_alphanumeric(),
+            CharSet::Printable => c.is_ascii_graphic() || c == ' ',
+        }
+    }
+}
+
+/// How a scanner's character stream is cut into barcodes, from
+/// `Config::barcode_framing`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct BarcodeFraming {
+    /// Characters that end a barcode.
+    pub terminators: Vec<Terminator>,
+    /// A barcode also ends after this long without input, for scanners
+    /// that send no terminator.
+    pub timeout_ms: u64,
+    pub min_len: usize,
+    pub max_len: usize,
+    pub allowed: CharSet,
+}
+
+impl Default for BarcodeFraming {
+    fn default() -> Self {
+        Self {
+            terminators: vec![Terminator::Cr, Terminator::Lf, Terminator::Tab],
+            timeout_ms: 150,
+            min_len: 4,
+            // Room for QR visitor passes
+            max_len: 512,
+            allowed: CharSet::Printable,
+        }
+    }
+}
+
+impl BarcodeFraming {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    /// Check a complete barcode against the length limits and character set.
+    pub fn check(&self, data: &str) -> Result<(), String> {
+        let len = data.chars().count();
+        if len < self.min_len || len > self.max_len {
+            return Err(format!(
+                "length {} outside {}..={}",
+                len, self.min_len, self.max_len
+            ));
+        }
+        match data.chars().find(|&c| !self.allowed.allows(c)) {
+            Some(c) => Err(format!("character {:?} not allowed", c)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Accumulates scanner input and yields each barcode once it is terminated
+/// or the scanner goes quiet.
+pub struct BarcodeFramer {
+    config: BarcodeFraming,
+    buffer: String,
+    last_input: Instant,
+}
+
+impl BarcodeFramer {
+    pub fn new(config: BarcodeFraming) -> Self {
+        Self {
+            config,
+            buffer: String::new(),
+            last_input: Instant::now(),
+        }
+    }
+
+    /// Feed received characters. Returns the barcodes they completed, each
+    /// checked against the framing rules.
+    pub fn push(&mut self, input: &str, now: Instant) -> Vec<Result<String, String>> {
+        let mut frames = Vec::new();
+        if let Some(frame) = self.flush_if_idle(now) {
+            frames.push(frame);
+        }
+        self.last_input = now;
+        for c in input.chars() {
+            if self.config.terminators.iter().any(|t| t.char() == c) {
+                if let Some(frame) = self.take() {
+                    frames.push(frame);
+                }
+            } else if !c.is_control() {
+                // Control characters are report padding, not barcode data
+                self.buffer.push(c);
+            }
+        }
+        frames
+    }
+
+    /// Complete the buffered barcode if nothing arrived within the timeout.
+    pub fn flush_if_idle(&mut self, now: Instant) -> Option<Result<String, String>> {
+        if now.duration_since(self.last_input) < self.config.timeout() {
+            return None;
+        }
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<Result<String, String>> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let data = std::mem::take(&mut self.buffer);
+        Some(match self.config.check(&data) {
+            Ok(()) => Ok(data),
+            Err(reason) => Err(format!("{} ({:?})", reason, data)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_on_terminators_and_timeouts_without_truncating() {
+        let mut framer = BarcodeFramer::new(BarcodeFraming::default());
+        let start = Instant::now();
+
+        assert!(framer.push("12345", start).is_empty());
+        assert!(framer.push("67890", start).is_empty());
+        assert_eq!(
+            framer.push("\r\n", start),
+            vec![Ok("1234567890".to_string())]
+        );
+
+        // No terminator: the pause before the next scan ends the first one
+        assert!(framer.push("AB-1234\0\0", start).is_empty());
+        assert_eq!(
+            framer.flush_if_idle(start + Duration::from_millis(50)),
+            None
+        );
+        let later = start + Duration::from_millis(200);
+        assert_eq!(framer.push("99", later), vec![Ok("AB-1234".to_string())]);
+        assert_eq!(
+            framer.flush_if_idle(later + Duration::from_millis(150)),
+            Some(Err("length 2 outside 4..=512 (\"99\")".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_characters_outside_the_allowed_set() {
+        let framing = BarcodeFraming {
+            terminators: vec![Terminator::Tab],
+            allowed: CharSet::Digits,
+            max_len: 9,
+            ..BarcodeFraming::default()
+        };
+        let mut framer = BarcodeFramer::new(framing.clone());
+        let now = Instant::now();
+
+        assert_eq!(framer.push("1234567\t", now), vec![Ok("1234567".into())]);
+        assert!(framer.push("12A4567\t", now)[0].is_err());
+        assert!(framer.push("1234567890\t", now)[0].is_err());
+        // Enter isn't a terminator here
+        assert!(framer.push("123\r4567", now).is_empty());
+        assert!(framing.check("12345678").is_ok());
+    }
+}
@@ -0,0 +1,127 @@
+use serde::Serialize;
+
+/// HID usage page of Point-of-Sale barcode scanners.
+pub const HID_POS_USAGE_PAGE: u16 = 0x8c;
+/// Report ID of the Scanned Data Report.
+const SCANNED_DATA_REPORT: u8 = 0x02;
+/// Report layout: ID, data length, 3-byte AIM symbology identifier, data.
+const LENGTH: usize = 1;
+const SYMBOLOGY_ID: std::ops::Range<usize> = 2..5;
+const DATA: usize = 5;
+const MAX_DATA_LEN: usize = 56;
+/// Last byte of the report; bit 0 is set when the barcode continues in the next report.
+const CONTINUED: usize = 63;
+
+/// Barcode symbology, from the AIM identifier the scanner reports.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Symbology {
+    Code128,
+    Code39,
+    Pdf417,
+    Qr,
+    DataMatrix,
+    EanUpc,
+    Itf,
+    Codabar,
+    Unknown,
+}
+
+impl Symbology {
+    /// Parse an AIM identifier such as `]C0` or `]Q1`.
+    pub fn from_aim_id(id: &str) -> Self {
+        let mut chars = id.chars();
+        if chars.next() != Some(']') {
+            return Symbology::Unknown;
+        }
+        match chars.next() {
+            Some('C') => Symbology::Code128,
+            Some('A') => Symbology::Code39,
+            Some('L') => Symbology::Pdf417,
+            Some('Q') => Symbology::Qr,
+            Some('d') => Symbology::DataMatrix,
+            Some('E') => Symbology::EanUpc,
+            Some('I') => Symbology::Itf,
+            Some('F') => Symbology::Codabar,
+            _ => Symbology::Unknown,
+        }
+    }
+}
+
+/// A complete barcode read from a HID POS scanner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannedData {
+    pub symbology: Symbology,
+    pub data: Vec<u8>,
+}
+
+/// Joins Scanned Data Reports into whole barcodes. Long codes such as
+/// PDF417 and QR span several reports.
+#[derive(Default)]
+pub struct PosReportAssembler {
+    symbology: Option<Symbology>,
+    data: Vec<u8>,
+}
+
+impl PosReportAssembler {
+    /// Add a report and return the barcode once its last report is in.
+    /// Reports other than Scanned Data Reports are ignored.
+    pub fn push(&mut self, report: &[u8]) -> Option<ScannedData> {
+        if report.len() <= DATA || report[0] != SCANNED_DATA_REPORT {
+            return None;
+        }
+        let len = usize::from(report[LENGTH])
+            .min(MAX_DATA_LEN)
+            .min(report.len() - DATA);
+        if self.symbology.is_none() {
+            let id: String = report[SYMBOLOGY_ID].iter().map(|&b| b as char).collect();
+            self.symbology = Some(Symbology::from_aim_id(&id));
+        }
+        self.data.extend_from_slice(&report[DATA..DATA + len]);
+        if report.get(CONTINUED).is_some_and(|flags| flags & 0x01 != 0) {
+            return None;
+        }
+        Some(ScannedData {
+            symbology: self.symbology.take().unwrap_or(Symbology::Unknown),
+            data: std::mem::take(&mut self.data),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(aim: &[u8; 3], data: &[u8], continued: bool) -> [u8; 64] {
+        let mut report = [0u8; 64];
+        report[0] = SCANNED_DATA_REPORT;
+        report[LENGTH] = data.len() as u8;
+        report[SYMBOLOGY_ID].copy_from_slice(aim);
+        report[DATA..DATA + data.len()].copy_from_slice(data);
+        report[CONTINUED] = continued as u8;
+        report
+    }
+
+    #[test]
+    fn assembles_single_and_multi_report_barcodes() {
+        let mut assembler = PosReportAssembler::default();
+        assert_eq!(
+            assembler.push(&report(b"]C0", b"1234567", false)),
+            Some(ScannedData {
+                symbology: Symbology::Code128,
+                data: b"1234567".to_vec(),
+            })
+        );
+
+        let first = [b'A'; MAX_DATA_LEN];
+        assert_eq!(assembler.push(&report(b"]Q1", &first, true)), None);
+        let scan = assembler.push(&report(b"]Q1", b"END", false)).unwrap();
+        assert_eq!(scan.symbology, Symbology::Qr);
+        assert_eq!(scan.data.len(), MAX_DATA_LEN + 3);
+        assert!(scan.data.ends_with(b"AEND"));
+
+        // Status reports and short reads are ignored
+        assert_eq!(assembler.push(&[0x04, 0x01]), None);
+        assert_eq!(Symbology::from_aim_id("\0\0\0"), Symbology::Unknown);
+    }
+}
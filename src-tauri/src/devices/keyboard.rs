@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize};
+
+/// Left or right Shift in the modifier byte.
+const SHIFT: u8 = 0x02 | 0x20;
+/// Right Alt, which is AltGr on European layouts.
+const ALT_GR: u8 = 0x40;
+/// Keycode sent in every slot when too many keys are held down.
+const ROLLOVER_ERROR: u8 = 0x01;
+
+/// Keyboard layout a keyboard-wedge scanner is programmed for.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyboardLayout {
+    #[default]
+    Us,
+    Uk,
+    /// German QWERTZ.
+    De,
+    /// French AZERTY.
+    Fr,
+}
+
+/// Keys that type the same character on every layout. Enter and Tab come
+/// out as `\r` and `\t`, which scanners send after each code.
+fn fixed(usage: u8) -> Option<char> {
+    Some(match usage {
+        0x28 | 0x58 => '\r',
+        0x2b => '\t',
+        0x54 => '/',
+        0x55 => '*',
+        0x56 => '-',
+        0x57 => '+',
+        0x59..=0x61 => char::from(b'1' + (usage - 0x59)),
+        0x62 => '0',
+        0x63 => '.',
+        _ => return None,
+    })
+}
+
+fn us_symbol(usage: u8) -> Option<(char, char)> {
+    Some(match usage {
+        0x1e => ('1', '!'),
+        0x1f => ('2', '@'),
+        0x20 => ('3', '#'),
+        0x21 => ('4', '$'),
+        0x22 => ('5', '%'),
+        0x23 => ('6', '^'),
+        0x24 => ('7', '&'),
+        0x25 => ('8', '*'),
+        0x26 => ('9', '('),
+        0x27 => ('0', ')'),
+        0x2c => (' ', ' '),
+        0x2d => ('-', '_'),
+        0x2e => ('=', '+'),
+        0x2f => ('[', '{'),
+        0x30 => (']', '}'),
+        0x31 => ('\\', '|'),
+        0x32 => ('#', '~'),
+        0x33 => (';', ':'),
+        0x34 => ('\'', '"'),
+        0x35 => ('`', '~'),
+        0x36 => (',', '<'),
+        0x37 => ('.', '>'),
+        0x38 => ('/', '?'),
+        0x64 => ('\\', '|'),
+        _ => return None,
+    })
+}
+
+impl KeyboardLayout {
+    /// Letter on the key with the given usage ID, in lower case.
+    fn letter(self, usage: u8) -> Option<char> {
+        let remapped = match (self, usage) {
+            (KeyboardLayout::De, 0x1c) => 'z',
+            (KeyboardLayout::De, 0x1d) => 'y',
+            (KeyboardLayout::Fr, 0x04) => 'q',
+            (KeyboardLayout::Fr, 0x14) => 'a',
+            (KeyboardLayout::Fr, 0x1a) => 'z',
+            (KeyboardLayout::Fr, 0x1d) => 'w',
+            (KeyboardLayout::Fr, 0x33) => 'm',
+            (KeyboardLayout::Fr, 0x10) => return None,
+            (_, 0x04..=0x1d) => char::from(b'a' + (usage - 0x04)),
+            _ => return None,
+        };
+        Some(remapped)
+    }
+
+    /// Unshifted and shifted character of a non-letter key.
+    fn symbol(self, usage: u8) -> Option<(char, char)> {
+        let symbol = match (self, usage) {
+            (KeyboardLayout::Uk, 0x1f) => ('2', '"'),
+            (KeyboardLayout::Uk, 0x20) => ('3', '£'),
+            (KeyboardLayout::Uk, 0x34) => ('\'', '@'),
+            (KeyboardLayout::Uk, 0x35) => ('`', '¬'),
+            (KeyboardLayout::De, 0x1f) => ('2', '"'),
+            (KeyboardLayout::De, 0x20) => ('3', '§'),
+            (KeyboardLayout::De, 0x23) => ('6', '&'),
+            (KeyboardLayout::De, 0x24) => ('7', '/'),
+            (KeyboardLayout::De, 0x25) => ('8', '('),
+            (KeyboardLayout::De, 0x26) => ('9', ')'),
+            (KeyboardLayout::De, 0x27) => ('0', '='),
+            (KeyboardLayout::De, 0x2d) => ('ß', '?'),
+            (KeyboardLayout::De, 0x2e) => ('´', '`'),
+            (KeyboardLayout::De, 0x2f) => ('ü', 'Ü'),
+            (KeyboardLayout::De, 0x30) => ('+', '*'),
+            (KeyboardLayout::De, 0x32) => ('#', '\''),
+            (KeyboardLayout::De, 0x33) => ('ö', 'Ö'),
+            (KeyboardLayout::De, 0x34) => ('ä', 'Ä'),
+            (KeyboardLayout::De, 0x35) => ('^', '°'),
+            (KeyboardLayout::De, 0x36) => (',', ';'),
+            (KeyboardLayout::De, 0x37) => ('.', ':'),
+            (KeyboardLayout::De, 0x38) => ('-', '_'),
+            (KeyboardLayout::De, 0x64) => ('<', '>'),
+            // AZERTY needs Shift for digits
+            (KeyboardLayout::Fr, 0x1e) => ('&', '1'),
+            (KeyboardLayout::Fr, 0x1f) => ('é', '2'),
+            (KeyboardLayout::Fr, 0x20) => ('"', '3'),
+            (KeyboardLayout::Fr, 0x21) => ('\'', '4'),
+            (KeyboardLayout::Fr, 0x22) => ('(', '5'),
+            (KeyboardLayout::Fr, 0x23) => ('-', '6'),
+            (KeyboardLayout::Fr, 0x24) => ('è', '7'),
+            (KeyboardLayout::Fr, 0x25) => ('_', '8'),
+            (KeyboardLayout::Fr, 0x26) => ('ç', '9'),
+            (KeyboardLayout::Fr, 0x27) => ('à', '0'),
+            (KeyboardLayout::Fr, 0x10) => (',', '?'),
+            (KeyboardLayout::Fr, 0x2d) => (')', '°'),
+            (KeyboardLayout::Fr, 0x2f) => ('^', '¨'),
+            (KeyboardLayout::Fr, 0x30) => ('$', '£'),
+            (KeyboardLayout::Fr, 0x32) => ('*', 'µ'),
+            (KeyboardLayout::Fr, 0x34) => ('ù', '%'),
+            (KeyboardLayout::Fr, 0x35) => ('²', '²'),
+            (KeyboardLayout::Fr, 0x36) => (';', '.'),
+            (KeyboardLayout::Fr, 0x37) => (':', '/'),
+            (KeyboardLayout::Fr, 0x38) => ('!', '§'),
+            (KeyboardLayout::Fr, 0x64) => ('<', '>'),
+            _ => return us_symbol(usage),
+        };
+        Some(symbol)
+    }
+
+    fn alt_gr(self, usage: u8) -> Option<char> {
+        Some(match (self, usage) {
+            (KeyboardLayout::De, 0x14) => '@',
+            (KeyboardLayout::De, 0x24) => '{',
+            (KeyboardLayout::De, 0x25) => '[',
+            (KeyboardLayout::De, 0x26) => ']',
+            (KeyboardLayout::De, 0x27) => '}',
+            (KeyboardLayout::De, 0x2d) => '\\',
+            (KeyboardLayout::De, 0x30) => '~',
+            (KeyboardLayout::De, 0x64) => '|',
+            (KeyboardLayout::Fr, 0x1f) => '~',
+            (KeyboardLayout::Fr, 0x20) => '#',
+            (KeyboardLayout::Fr, 0x21) => '{',
+            (KeyboardLayout::Fr, 0x22) => '[',
+            (KeyboardLayout::Fr, 0x23) => '|',
+            (KeyboardLayout::Fr, 0x24) => '`',
+            (KeyboardLayout::Fr, 0x25) => '\\',
+            (KeyboardLayout::Fr, 0x26) => '^',
+            (KeyboardLayout::Fr, 0x27) => '@',
+            (KeyboardLayout::Fr, 0x2d) => ']',
+            (KeyboardLayout::Fr, 0x2e) => '}',
+            _ => return None,
+        })
+    }
+
+    /// Character typed by a key press, if the key produces one.
+    pub fn char_for(self, usage: u8, modifiers: u8) -> Option<char> {
+        if let Some(c) = fixed(usage) {
+            return Some(c);
+        }
+        if modifiers & ALT_GR != 0 {
+            return self.alt_gr(usage);
+        }
+        let shift = modifiers & SHIFT != 0;
+        if let Some(letter) = self.letter(usage) {
+            return Some(if shift {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            });
+        }
+        let (plain, shifted) = self.symbol(usage)?;
+        Some(if shift { shifted } else { plain })
+    }
+}
+
+/// Turns HID boot-keyboard reports (`[modifiers, reserved, key1..key6]`)
+/// into typed characters.
+///
+/// A key counts when it first appears in a report, so holding it doesn't
+/// repeat and the same character twice in a row needs a release in between,
+/// as on a real keyboard.
+pub struct BootKeyboardDecoder {
+    layout: KeyboardLayout,
+    held: Vec<u8>,
+}
+
+impl BootKeyboardDecoder {
+    pub fn new(layout: KeyboardLayout) -> Self {
+        Self {
+            layout,
+            held: Vec::new(),
+        }
+    }
+
+    pub fn decode(&mut self, report: &[u8]) -> String {
+        // Devices using numbered reports put the report ID first
+        let report = if report.len() == 9 {
+            &report[1..]
+        } else {
+            report
+        };
+        if report.len() < 3 {
+            return String::new();
+        }
+        let modifiers = report[0];
+        let keys: Vec<u8> = report[2..].iter().copied().filter(|&k| k != 0).collect();
+        if keys.contains(&ROLLOVER_ERROR) {
+            return String::new();
+        }
+        let typed = keys
+            .iter()
+            .filter(|k| !self.held.contains(k))
+            .filter_map(|&usage| self.layout.char_for(usage, modifiers))
+            .collect();
+        self.held = keys;
+        typed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(decoder: &mut BootKeyboardDecoder, reports: &[[u8; 8]]) -> String {
+        reports
+            .iter()
+            .map(|report| decoder.decode(report))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_shift_repeats_and_terminators() {
+        let release = [0u8; 8];
+        let mut decoder = BootKeyboardDecoder::new(KeyboardLayout::Us);
+        let text = typed(
+            &mut decoder,
+            &[
+                [0x02, 0, 0x0b, 0, 0, 0, 0, 0], // H
+                release,
+                [0, 0, 0x1e, 0, 0, 0, 0, 0], // 1
+                [0, 0, 0x1e, 0, 0, 0, 0, 0], // still held
+                release,
+                [0, 0, 0x1e, 0, 0, 0, 0, 0], // 1 again
+                release,
+                [0x20, 0, 0x1f, 0, 0, 0, 0, 0], // @ with right Shift
+                [0, 0, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01],
+                release,
+                [0, 0, 0x28, 0, 0, 0, 0, 0],
+                release,
+            ],
+        );
+        assert_eq!(text, "H11@\r");
+    }
+
+    #[test]
+    fn applies_the_configured_layout() {
+        let mut de = BootKeyboardDecoder::new(KeyboardLayout::De);
+        let text = typed(
+            &mut de,
+            &[
+                [0, 0, 0x1c, 0, 0, 0, 0, 0],    // z on QWERTZ
+                [0x40, 0, 0x14, 0, 0, 0, 0, 0], // AltGr+Q
+                [0, 0, 0x2b, 0, 0, 0, 0, 0],    // Tab
+            ],
+        );
+        assert_eq!(text, "z@\t");
+
+        // AZERTY scanners send Shift with every digit
+        let mut fr = BootKeyboardDecoder::new(KeyboardLayout::Fr);
+        let text = typed(
+            &mut fr,
+            &[
+                [0x02, 0, 0x1e, 0, 0, 0, 0, 0],
+                [0x02, 0, 0x27, 0, 0, 0, 0, 0],
+                [0, 0, 0x1e, 0, 0, 0, 0, 0],
+                [0, 0, 0x62, 0, 0, 0, 0, 0], // keypad 0
+            ],
+        );
+        assert_eq!(text, "10&0");
+    }
+}
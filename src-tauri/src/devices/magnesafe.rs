@@ -0,0 +1,177 @@
+use crate::error::GuestbookError;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::ops::Range;
+
+// Field layout of the MagneSafe HID input report (MagTek encrypting readers
+// such as DynaMag and eDynamo in HID mode). Offsets are in bytes.
+const TRACK_STATUS: usize = 0;
+const ENCRYPTED_LEN: usize = 3;
+const ENCRYPTED_DATA: [usize; 3] = [7, 119, 231];
+const MAGNEPRINT_STATUS: Range<usize> = 343..347;
+const MAGNEPRINT_LEN: usize = 347;
+const MAGNEPRINT_DATA: usize = 348;
+const MAGNEPRINT_MAX: usize = 128;
+const DEVICE_SERIAL: Range<usize> = 476..492;
+const KSN: Range<usize> = 494..504;
+const MASKED_LEN: usize = 504;
+const MASKED_DATA: [usize; 3] = [507, 619, 731];
+/// Size of each track data field, encrypted or masked.
+const TRACK_FIELD_LEN: usize = 112;
+/// Reports shorter than this can't hold all masked tracks.
+pub const MIN_REPORT_LEN: usize = 843;
+
+/// Encrypted swipe data, forwarded to the server untouched (hex encoded) so
+/// it can be decrypted there with the DUKPT base key.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EncryptedSwipe {
+    /// DUKPT key serial number.
+    pub ksn: String,
+    pub device_serial: String,
+    /// Encrypted track 1-3 data; empty for tracks that weren't read.
+    pub tracks: [String; 3],
+    pub magneprint: String,
+    pub magneprint_status: String,
+}
+
+/// A decoded MagneSafe report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagnesafeReport {
+    /// Masked ASCII track data (sentinels included) for tracks that decoded.
+    pub masked_tracks: [Option<String>; 3],
+    pub encrypted: EncryptedSwipe,
+}
+
+impl MagnesafeReport {
+    /// Masked tracks joined the way a keyboard-mode reader would send them,
+    /// ready for the card profiles.
+    pub fn masked_text(&self) -> String {
+        self.masked_tracks
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut out, b| {
+            let _ = write!(out, "{:02X}", b);
+            out
+        })
+}
+
+/// `len` bytes at `start`, capped to the field size.
+fn field(report: &[u8], start: usize, len: u8, max: usize) -> &[u8] {
+    &report[start..start + usize::from(len).min(max)]
+}
+
+pub fn decode_report(report: &[u8]) -> Result<MagnesafeReport, GuestbookError> {
+    if report.len() < MIN_REPORT_LEN {
+        return Err(GuestbookError::Parse(format!(
+            "MagneSafe report too short: {} bytes",
+            report.len()
+        )));
+    }
+    let mut masked_tracks: [Option<String>; 3] = Default::default();
+    let mut encrypted_tracks: [String; 3] = Default::default();
+    for track in 0..3 {
+        // Non-zero decode status means the head didn't read this track
+        if report[TRACK_STATUS + track] != 0 {
+            continue;
+        }
+        let masked = field(
+            report,
+            MASKED_DATA[track],
+            report[MASKED_LEN + track],
+            TRACK_FIELD_LEN,
+        );
+        if !masked.is_empty() {
+            masked_tracks[track] = Some(masked.iter().map(|&b| b as char).collect());
+        }
+        encrypted_tracks[track] = hex(field(
+            report,
+            ENCRYPTED_DATA[track],
+            report[ENCRYPTED_LEN + track],
+            TRACK_FIELD_LEN,
+        ));
+    }
+    let device_serial = report[DEVICE_SERIAL]
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| b as char)
+        .collect::<String>();
+    Ok(MagnesafeReport {
+        masked_tracks,
+        encrypted: EncryptedSwipe {
+            ksn: hex(&report[KSN]),
+            device_serial: device_serial.trim().to_string(),
+            tracks: encrypted_tracks,
+            magneprint: hex(field(
+                report,
+                MAGNEPRINT_DATA,
+                report[MAGNEPRINT_LEN],
+                MAGNEPRINT_MAX,
+            )),
+            magneprint_status: hex(&report[MAGNEPRINT_STATUS]),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Copy `data` into `report` at `offset`.
+    fn put(report: &mut [u8], offset: usize, data: &[u8]) {
+        report[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    #[test]
+    fn decodes_masked_tracks_encrypted_blocks_and_ksn() {
+        // Offsets straight from the MagneSafe HID report spec
+        let mut report = vec![0u8; 843];
+        let track1 = b"%B1234567   ^DOE/JOHN^0000000000000000?";
+        report[504] = track1.len() as u8;
+        put(&mut report, 507, track1);
+        report[3] = 8;
+        put(&mut report, 7, &[0xA5; 8]);
+        // Track 2 failed to decode; its leftover data must be ignored
+        report[1] = 1;
+        report[505] = 4;
+        put(&mut report, 619, b";12?");
+        // Track 3 ends exactly at the end of the report
+        report[506] = 112;
+        put(&mut report, 731, &[b'+'; 112]);
+        put(&mut report, 343, &[0x00, 0x01, 0x02, 0x03]);
+        report[347] = 2;
+        put(&mut report, 348, &[0x5A, 0x5B]);
+        put(&mut report, 476, b"B1234567");
+        put(
+            &mut report,
+            494,
+            &[0xFF, 0xFF, 0x98, 0x76, 0x54, 0x32, 0x10, 0xE0, 0x00, 0x01],
+        );
+
+        let decoded = decode_report(&report).unwrap();
+        assert_eq!(
+            decoded.masked_tracks[0].as_deref(),
+            Some("%B1234567   ^DOE/JOHN^0000000000000000?")
+        );
+        assert_eq!(decoded.masked_tracks[1], None);
+        assert_eq!(
+            decoded.masked_tracks[2].as_ref().map(String::len),
+            Some(112)
+        );
+        assert_eq!(decoded.encrypted.tracks[0], "A5A5A5A5A5A5A5A5");
+        assert_eq!(decoded.encrypted.tracks[1], "");
+        assert_eq!(decoded.encrypted.magneprint_status, "00010203");
+        assert_eq!(decoded.encrypted.magneprint, "5A5B");
+        assert_eq!(decoded.encrypted.device_serial, "B1234567");
+        assert_eq!(decoded.encrypted.ksn, "FFFF9876543210E00001");
+
+        assert!(decode_report(&report[..842]).is_err());
+    }
+}
@@ -1,143 +1,124 @@
-use hidapi::{HidApi, HidDevice};
-use regex::Regex;
+use crate::config::config_manager::Config;
+use crate::devices::dispatcher::{EventSink, ReaderDecoder};
+use crate::devices::keyboard::BootKeyboardDecoder;
+use crate::devices::magnesafe::{decode_report, EncryptedSwipe};
+use crate::devices::profiles::{load_profiles, parse_card_data, CardProfile};
+use crate::devices::registry::{ReaderMatch, ReportFormat};
 use serde::Serialize;
-use tauri::{Emitter, Window};
-use log::{info, warn, error};
-use std::time::Duration;
+use log::{info, warn};
+use std::time::{Duration, Instant};
 
-lazy_static::lazy_static! {
-    static ref ID_RE: Regex = Regex::new(r"(\d{7})\s{3}").unwrap();
-    static ref NAME_RE: Regex = Regex::new(r"\^([^^]*)\^").unwrap();
-}
+/// Quiet time after which buffered reader output is treated as one complete swipe.
+const SWIPE_GAP: Duration = Duration::from_millis(150);
 
 #[derive(Serialize, Clone)]
 pub struct CardData {
     pub onecard: String,
     pub name: String,
+    pub tracks_read: Vec<u8>,
+    /// Card profile that recognised the swipe.
+    pub profile: String,
+    /// Encrypted block and KSN from a MagneSafe reader, passed on to the server as-is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<EncryptedSwipe>,
 }
 
-fn parse_card_data(data: &str) -> Option<CardData> {
-    let track1 = data.trim().split('?').next().unwrap_or(data);
-    // Extract name between first two '^'
-    let name = track1.split('^').nth(1)?.trim().to_string();
-    // Find all 7-digit numbers in track1, take the last one
-    let id_re = Regex::new(r"(\d{7})\s{3}").ok()?;
-    let onecard = id_re
-        .captures_iter(track1)
-        .last()?
-        .get(1)?
-        .as_str()
-        .to_string();
-    println!(
-        "parse_card_data: name = {:?}, onecard = {:?}",
-        name, onecard
-    );
-    Some(CardData { onecard, name })
+fn handle_swipe(sink: &dyn EventSink, profiles: &[CardProfile], raw: &str) {
+    let raw = raw.trim();
+    if let Some(card) = parse_card_data(profiles, raw) {
+        info!(
+            "MagTek card swiped: {} - {} (profile {}, tracks {:?})",
+            card.onecard, card.name, card.profile, card.tracks_read
+        );
+        sink.emit("magtek-data", card);
+    } else {
+        warn!("MagTek swipe data could not be parsed: {}", raw);
+        sink.emit("hid-data", raw);
+    }
 }
 
-pub fn listen_to_magtek(device: HidDevice, window: Window) {
-    info!("Starting MagTek reader listener thread");
-    std::thread::spawn(move || {
-        let mut buffer = [0u8; 256];
-        let mut scan_buffer = String::new();
-        let mut consecutive_errors = 0;
-        let max_consecutive_errors = 5;
-
-        loop {
-            match device.read(&mut buffer) {
-                Ok(size) => {
-                    consecutive_errors = 0; // Reset error counter on successful read
-                    let mut part: String = buffer[..size]
-                        .iter()
-                        .filter(|&&b| b != 0)
-                        .map(|&b| b as char)
-                        .collect();
-
-                    // Strip N' prefix if present before accumulating
-                    if let Some(idx) = part.find('%') {
-                        if idx >= 2 && &part[idx - 2..idx] == "N'" {
-                            part = part[idx..].to_string();
-                        }
-                    }
-
-                    // If a new swipe starts before the previous one finished, reset the buffer
-                    if !scan_buffer.is_empty() && part.contains('%') {
-                        scan_buffer.clear();
-                    }
-
-                    // Start accumulating on the first '%'
-                    if scan_buffer.is_empty() {
-                        if let Some(percent_idx) = part.find('%') {
-                            scan_buffer = part[percent_idx..].to_string();
-                        }
-                    } else {
-                        scan_buffer.push_str(&part);
-                    }
-
-                    // Only accept '?' as end-of-track
-                    if let Some(end) = scan_buffer.find('?') {
-                        let track1 = &scan_buffer[..=end];
-                        let cleaned = track1.trim();
-                        if let Some(card) = parse_card_data(cleaned) {
-                            info!("MagTek card swiped: {} - {}", card.onecard, card.name);
-                            window.emit("magtek-data", card).ok();
-                        } else {
-                            warn!("MagTek swipe data could not be parsed: {}", cleaned);
-                            window.emit("hid-data", cleaned.to_string()).ok();
-                        }
-                        scan_buffer.clear();
-                    }
-                }
-                Err(e) => {
-                    consecutive_errors += 1;
-                    warn!("MagTek reader read error (attempt {}/{}): {}", consecutive_errors, max_consecutive_errors, e);
-
-                    if consecutive_errors >= max_consecutive_errors {
-                        error!("MagTek reader failed after {} consecutive errors, stopping listener", max_consecutive_errors);
-                        window.emit("hid-error", format!("MagTek reader failed: {}", e)).ok();
-                        break;
-                    }
-
-                    // Wait a bit before retrying
-                    std::thread::sleep(Duration::from_millis(100));
-                }
-            }
+/// Look up the ID from the masked tracks and attach the encrypted data.
+fn handle_magnesafe_report(sink: &dyn EventSink, profiles: &[CardProfile], report: &[u8]) {
+    let decoded = match decode_report(report) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            warn!("Ignoring MagneSafe report: {}", e);
+            return;
         }
-    });
+    };
+    let masked = decoded.masked_text();
+    if let Some(mut card) = parse_card_data(profiles, &masked) {
+        info!(
+            "MagneSafe card swiped: {} - {} (profile {}, tracks {:?}, KSN {})",
+            card.onecard, card.name, card.profile, card.tracks_read, decoded.encrypted.ksn
+        );
+        card.encrypted = Some(decoded.encrypted);
+        sink.emit("magtek-data", card);
+    } else {
+        warn!("MagneSafe masked track data could not be parsed: {}", masked);
+        sink.emit("hid-data", masked);
+    }
 }
 
-pub fn open_magtek_reader(api: &HidApi) -> Option<HidDevice> {
-    for device in api.device_list() {
-        let vendor_match = device.vendor_id() == 0x0801;
-        let manufacturer = device.manufacturer_string().unwrap_or_default();
-        let product = device.product_string().unwrap_or_default();
-
-        let name_match = manufacturer.contains("MagTek")
-            || manufacturer.contains("Mag-Tek")
-            || product.contains("MagTek");
+/// Gathers a swipe reader's output into whole swipes and emits the card
+/// data, or decodes MagneSafe reports as they come.
+pub struct SwipeDecoder {
+    profiles: Vec<CardProfile>,
+    report_format: ReportFormat,
+    keyboard: Option<BootKeyboardDecoder>,
+    scan_buffer: String,
+}
 
-        if vendor_match || name_match {
-            return api.open_path(device.path()).ok();
+impl SwipeDecoder {
+    pub fn new(config: &Config, reader: &ReaderMatch) -> Self {
+        let profiles = load_profiles(&config.card_profiles(), reader.parser_profile.as_deref());
+        if profiles.is_empty() {
+            warn!("No usable card profiles configured, swipes will not be recognised");
+        }
+        Self {
+            profiles,
+            report_format: reader.report_format,
+            keyboard: (reader.report_format == ReportFormat::BootKeyboard)
+                .then(|| BootKeyboardDecoder::new(reader.keyboard_layout)),
+            scan_buffer: String::new(),
         }
     }
-    None
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl ReaderDecoder for SwipeDecoder {
+    fn idle_timeout(&self) -> Duration {
+        SWIPE_GAP
+    }
 
-    #[test]
-    fn parse_valid_track() {
-        let data = "%B1234567   ^DOE/JOHN^1234567890123456?";
-        let card = parse_card_data(data).expect("Should parse");
-        assert_eq!(card.onecard, "1234567");
-        assert_eq!(card.name, "DOE/JOHN");
+    fn frame(&mut self, data: &[u8], _at: Instant, sink: &dyn EventSink) {
+        if self.report_format == ReportFormat::Magnesafe {
+            // Each report carries a complete swipe
+            handle_magnesafe_report(sink, &self.profiles, data);
+            return;
+        }
+        let part: String = match self.keyboard.as_mut() {
+            Some(decoder) => decoder.decode(data),
+            None => data
+                .iter()
+                .filter(|&&b| b != 0)
+                .map(|&b| b as char)
+                .collect(),
+        };
+
+        // Start accumulating on the first start sentinel, which also drops the N' prefix
+        if self.scan_buffer.is_empty() {
+            if let Some(start) = part.find(['%', ';', '+']) {
+                self.scan_buffer = part[start..].to_string();
+            }
+        } else {
+            self.scan_buffer.push_str(&part);
+        }
     }
 
-    #[test]
-    fn parse_invalid_track() {
-        let data = "%B12^BAD^";
-        assert!(parse_card_data(data).is_none());
+    fn idle(&mut self, _at: Instant, sink: &dyn EventSink) {
+        // Nothing arrived within the gap, so all tracks of the swipe are in
+        if !self.scan_buffer.is_empty() {
+            handle_swipe(sink, &self.profiles, &std::mem::take(&mut self.scan_buffer));
+        }
     }
 }
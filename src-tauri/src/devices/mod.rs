@@ -1,2 +1,293 @@
 pub mod barcode;
+pub mod capture;
+pub mod contactless;
+pub mod dispatcher;
+#[cfg(target_os = "linux")]
+pub mod evdev;
+pub mod framing;
+pub mod hid_pos;
+pub mod keyboard;
+pub mod magnesafe;
 pub mod magtek;
+pub mod normalize;
+pub mod profiles;
+pub mod registry;
+pub mod serial;
+pub mod simulated;
+pub mod source;
+pub mod supervisor;
+#[cfg(test)]
+pub mod test_support;
+pub mod tracks;
+pub mod visitor_pass;
+
+use crate::config::config_manager::{get_full_config, Config, ConfigManager};
+use crate::error::GuestbookError;
+use barcode::BarcodeDecoder;
+use contactless::TapDecoder;
+use dispatcher::{Dispatcher, ReaderDecoder};
+use hidapi::HidApi;
+use magtek::SwipeDecoder;
+use registry::{locate_reader, ReaderMatch, ReaderRole, ReportFormat, SourceKind};
+use serde::Serialize;
+use source::{HidSource, InputSource};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+
+/// Last known state of a reader listener, reported in heartbeat telemetry.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", content = "detail", rename_all = "snake_case")]
+pub enum ReaderState {
+    NotFound,
+    Running,
+    Stopped(String),
+}
+
+/// States and generations of the reader listeners. The app shares one,
+/// `LISTENERS`; tests give each dispatcher its own.
+#[derive(Default)]
+pub struct Listeners {
+    states: Mutex<BTreeMap<&'static str, ReaderState>>,
+    /// Incremented each time a listener starts, so a listener that was
+    /// replaced after a replug can't overwrite the new one's state.
+    generations: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Listeners {
+    pub fn set_state(&self, reader: &'static str, state: ReaderState) {
+        self.states.lock().unwrap().insert(reader, state);
+    }
+
+    pub fn states(&self) -> BTreeMap<&'static str, ReaderState> {
+        self.states.lock().unwrap().clone()
+    }
+
+    /// Mark a new listener for `reader` as running and return its generation.
+    pub fn started(&self, reader: &'static str) -> u64 {
+        let mut generations = self.generations.lock().unwrap();
+        let generation = generations.entry(reader).or_default();
+        *generation += 1;
+        self.set_state(reader, ReaderState::Running);
+        *generation
+    }
+
+    /// Whether `generation` is still the newest listener for `reader`.
+    pub fn is_current(&self, reader: &'static str, generation: u64) -> bool {
+        self.generations.lock().unwrap().get(reader) == Some(&generation)
+    }
+
+    /// Record that a listener gave up. Returns false if a newer listener has
+    /// already replaced it, in which case nothing is changed.
+    pub fn stopped(&self, reader: &'static str, generation: u64, reason: String) -> bool {
+        let generations = self.generations.lock().unwrap();
+        if generations.get(reader) != Some(&generation) {
+            return false;
+        }
+        self.set_state(reader, ReaderState::Stopped(reason));
+        true
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref LISTENERS: Arc<Listeners> = Arc::new(Listeners::default());
+    /// Serializes reader startup between the frontend and the supervisor.
+    static ref START_LOCK: Mutex<()> = Mutex::new(());
+}
+
+pub fn set_reader_state(reader: &'static str, state: ReaderState) {
+    LISTENERS.set_state(reader, state);
+}
+
+pub fn reader_states() -> BTreeMap<&'static str, ReaderState> {
+    LISTENERS.states()
+}
+
+/// A listener the app can run, fed by the registry entries with its role.
+pub struct ReaderKind {
+    pub name: &'static str,
+    pub role: ReaderRole,
+    label: &'static str,
+    decoder: fn(&Config, &ReaderMatch) -> Box<dyn ReaderDecoder>,
+}
+
+pub const READERS: [ReaderKind; 3] = [
+    ReaderKind {
+        name: "barcode",
+        role: ReaderRole::Barcode,
+        label: "barcode scanner",
+        decoder: |config, reader| Box::new(BarcodeDecoder::new(config, reader)),
+    },
+    ReaderKind {
+        name: "magtek",
+        role: ReaderRole::Swipe,
+        label: "MagTek reader",
+        decoder: |config, reader| Box::new(SwipeDecoder::new(config, reader)),
+    },
+    ReaderKind {
+        name: "contactless",
+        role: ReaderRole::Contactless,
+        label: "contactless reader",
+        decoder: |_, reader| Box::new(TapDecoder::new(reader)),
+    },
+];
+
+pub fn start_barcode_reader(app: AppHandle) -> Result<(), GuestbookError> {
+    start_reader(app, &READERS[0])
+}
+
+pub fn start_magtek_reader(app: AppHandle) -> Result<(), GuestbookError> {
+    start_reader(app, &READERS[1])
+}
+
+pub fn start_contactless_reader(app: AppHandle) -> Result<(), GuestbookError> {
+    start_reader(app, &READERS[2])
+}
+
+#[cfg(target_os = "linux")]
+fn evdev_source(entry: &ReaderMatch) -> Option<(Box<dyn InputSource>, ReaderMatch)> {
+    let path = evdev::locate_event_node(entry)?;
+    let mut entry = entry.clone();
+    // The source turns key events into boot-keyboard reports
+    entry.report_format = ReportFormat::BootKeyboard;
+    Some((Box::new(evdev::EvdevSource::new(path)), entry))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn evdev_source(entry: &ReaderMatch) -> Option<(Box<dyn InputSource>, ReaderMatch)> {
+    log::warn!("{} is an evdev reader, which needs Linux", entry.label());
+    None
+}
+
+fn serial_source(entry: &ReaderMatch) -> Option<(Box<dyn InputSource>, ReaderMatch)> {
+    let port = serial::locate_port(entry, &serial::available_ports())?;
+    let settings = entry.serial.clone().unwrap_or_default();
+    let source = serial::SerialSource::new(port.port_name, settings);
+    Some((Box::new(source), entry.clone()))
+}
+
+fn replay_source(entry: &ReaderMatch) -> Option<(Box<dyn InputSource>, ReaderMatch)> {
+    let path = entry.device_path.as_deref()?;
+    match capture::load_capture(std::path::Path::new(path)) {
+        Ok((source, mut recorded)) => {
+            recorded.role = entry.role;
+            Some((Box::new(source), recorded))
+        }
+        Err(e) => {
+            log::warn!("Cannot replay {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn pcsc_source(entry: &ReaderMatch) -> Option<(Box<dyn InputSource>, ReaderMatch)> {
+    let reader = contactless::locate_pcsc_reader(entry, &contactless::available_readers())?;
+    let source = contactless::PcscSource::new(reader, entry.id_file.clone());
+    Some((Box::new(source), entry.clone()))
+}
+
+/// The first registered reader with `role` that is attached, with the
+/// source its entry asks for.
+fn locate_source(
+    api: &HidApi,
+    registry: &[ReaderMatch],
+    role: ReaderRole,
+) -> Option<(Box<dyn InputSource>, ReaderMatch)> {
+    registry
+        .iter()
+        .filter(|entry| entry.role == role)
+        .find_map(|entry| match entry.source {
+            SourceKind::Hid => {
+                let (path, entry) = locate_reader(api, std::slice::from_ref(entry), role)?;
+                let source: Box<dyn InputSource> = Box::new(HidSource::new(path));
+                Some((source, entry))
+            }
+            SourceKind::Evdev => evdev_source(entry),
+            SourceKind::Serial => serial_source(entry),
+            SourceKind::Pcsc => pcsc_source(entry),
+            SourceKind::Replay => replay_source(entry),
+        })
+}
+
+/// Wrap `source` so its frames are also written to a capture file. A
+/// capture that can't be created doesn't keep the reader from starting.
+fn record(
+    source: Box<dyn InputSource>,
+    reader: &'static str,
+    entry: &ReaderMatch,
+    config_path: &std::path::Path,
+) -> Box<dyn InputSource> {
+    match capture::Capture::create(reader, entry, &capture::capture_dir(config_path)) {
+        Ok(capture) => Box::new(capture::RecordingSource::new(source, capture)),
+        Err(e) => {
+            log::warn!("Not recording {} input: {}", reader, e);
+            source
+        }
+    }
+}
+
+/// Open the first registered device for a reader and start its listener,
+/// unless one is already running.
+pub fn start_reader(app: AppHandle, kind: &ReaderKind) -> Result<(), GuestbookError> {
+    let _guard = START_LOCK.lock().unwrap();
+    if reader_states().get(kind.name) == Some(&ReaderState::Running) {
+        log::info!("{} listener is already running", kind.label);
+        return Ok(());
+    }
+    log::info!("Attempting to start {} listener", kind.label);
+    let config_manager = app.state::<ConfigManager>();
+    let config = get_full_config(config_manager.clone());
+    let api = HidApi::new().map_err(|e| {
+        log::error!("Failed to initialize HID API: {}", e);
+        GuestbookError::from(e)
+    })?;
+
+    let registry = config.reader_registry();
+    let located = if simulated::is_enabled(&config) {
+        simulated::simulated_source(&simulated::INPUTS, &registry, kind.name, kind.role)
+    } else {
+        locate_source(&api, &registry, kind.role)
+    };
+    match located {
+        Some((source, entry)) => {
+            log::info!(
+                "Found {} ({}), starting listener",
+                kind.label,
+                entry.label()
+            );
+            let decoder = (kind.decoder)(&config, &entry);
+            let source = if config.capture_readers() {
+                record(source, kind.name, &entry, &config_manager.config_path)
+            } else {
+                source
+            };
+            app.state::<Dispatcher>()
+                .attach(kind.name, entry.label(), source, decoder)
+                .inspect_err(|e| {
+                    log::warn!("Failed to open {}: {}", entry.label(), e);
+                    set_reader_state(kind.name, ReaderState::Stopped(e.to_string()));
+                })
+        }
+        None => {
+            log::warn!("No compatible {} found", kind.label);
+            set_reader_state(kind.name, ReaderState::NotFound);
+            Err(GuestbookError::Hid(format!(
+                "No compatible {} found.",
+                kind.label
+            )))
+        }
+    }
+}
+
+/// Start every reader whose listener isn't running, e.g. after a device was
+/// plugged back in. Returns the readers that are running afterwards.
+pub fn rescan_readers(app: &AppHandle) -> Vec<&'static str> {
+    let mut running = Vec::new();
+    for kind in &READERS {
+        match start_reader(app.clone(), kind) {
+            Ok(()) => running.push(kind.name),
+            Err(e) => log::warn!("Rescan could not start {} reader: {}", kind.name, e),
+        }
+    }
+    running
+}
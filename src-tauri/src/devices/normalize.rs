@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+
+/// Length of a canonical OneCard ID, as read from a swipe.
+pub const ONECARD_ID_LEN: usize = 7;
+
+/// Characters of Code 39 in Mod 43 value order.
+const CODE39_CHARS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ-. $/+%";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckAlgorithm {
+    /// Doubles every second digit, as on payment cards.
+    Luhn,
+    /// Weights 3 and 1, as in UPC/EAN and Interleaved 2 of 5.
+    Mod10,
+    /// Code 39 check character.
+    Mod43,
+}
+
+impl CheckAlgorithm {
+    /// Check character for `data`, or `None` if it has characters the
+    /// algorithm can't handle.
+    fn check_char(self, data: &str) -> Option<char> {
+        match self {
+            CheckAlgorithm::Luhn | CheckAlgorithm::Mod10 => {
+                let mut sum = 0;
+                // Weights start at the digit next to the check digit
+                for (i, c) in data.chars().rev().enumerate() {
+                    let digit = c.to_digit(10)?;
+                    sum += match (self, i % 2) {
+                        (CheckAlgorithm::Luhn, 0) => (digit * 2) / 10 + (digit * 2) % 10,
+                        (CheckAlgorithm::Mod10, 0) => digit * 3,
+                        _ => digit,
+                    };
+                }
+                char::from_digit((10 - sum % 10) % 10, 10)
+            }
+            CheckAlgorithm::Mod43 => {
+                let mut sum = 0;
+                for c in data.chars() {
+                    sum += CODE39_CHARS.find(c)?;
+                }
+                CODE39_CHARS.chars().nth(sum % 43)
+            }
+        }
+    }
+}
+
+/// One step of a barcode format, applied in order.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NormalizeStep {
+    /// Remove the text if the barcode starts with it.
+    StripPrefix {
+        prefix: String,
+    },
+    StripSuffix {
+        suffix: String,
+    },
+    /// Remove a fixed number of leading characters, e.g. an issue code.
+    DropLeading {
+        count: usize,
+    },
+    DropTrailing {
+        count: usize,
+    },
+    /// Fail unless the last character is the right check character, then
+    /// remove it unless `keep` is set.
+    CheckDigit {
+        algorithm: CheckAlgorithm,
+        #[serde(default)]
+        keep: bool,
+    },
+    /// Left-pad with zeros to `len` characters.
+    ZeroPad {
+        len: usize,
+    },
+}
+
+impl NormalizeStep {
+    fn apply(&self, mut data: String) -> Option<String> {
+        match self {
+            NormalizeStep::StripPrefix { prefix } => Some(
+                data.strip_prefix(prefix.as_str())
+                    .unwrap_or(&data)
+                    .to_string(),
+            ),
+            NormalizeStep::StripSuffix { suffix } => Some(
+                data.strip_suffix(suffix.as_str())
+                    .unwrap_or(&data)
+                    .to_string(),
+            ),
+            NormalizeStep::DropLeading { count } => {
+                let (start, _) = data.char_indices().nth(*count)?;
+                Some(data[start..].to_string())
+            }
+            NormalizeStep::DropTrailing { count } => {
+                let len = data.chars().count().checked_sub(*count)?;
+                Some(data.chars().take(len).collect())
+            }
+            NormalizeStep::CheckDigit { algorithm, keep } => {
+                let check = data.chars().last()?;
+                let body_len = data.len() - check.len_utf8();
+                if algorithm.check_char(&data[..body_len])? != check {
+                    return None;
+                }
+                if !keep {
+                    data.truncate(body_len);
+                }
+                Some(data)
+            }
+            NormalizeStep::ZeroPad { len } => Some(format!("{:0>len$}", data, len = *len)),
+        }
+    }
+}
+
+/// A printed barcode layout from `Config::barcode_formats`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BarcodeFormat {
+    pub name: String,
+    /// Only barcodes of exactly this many characters use the format.
+    #[serde(default)]
+    pub length: Option<usize>,
+    #[serde(default)]
+    pub steps: Vec<NormalizeStep>,
+}
+
+impl BarcodeFormat {
+    /// The canonical ID for `data`, or `None` if it doesn't fit this format.
+    pub fn normalize(&self, data: &str) -> Option<String> {
+        if self.length.is_some_and(|len| data.chars().count() != len) {
+            return None;
+        }
+        let id = self
+            .steps
+            .iter()
+            .try_fold(data.to_string(), |data, step| step.apply(data))?;
+        (id.len() == ONECARD_ID_LEN && id.chars().all(|c| c.is_ascii_digit())).then_some(id)
+    }
+}
+
+/// Formats used when `Config::barcode_formats` is not set: the bare OneCard
+/// number, and the printed card layout of a one-digit issue code, the
+/// number and a Mod 10 check digit.
+pub fn default_formats() -> Vec<BarcodeFormat> {
+    vec![
+        BarcodeFormat {
+            name: "onecard".to_string(),
+            length: Some(ONECARD_ID_LEN),
+            steps: Vec::new(),
+        },
+        BarcodeFormat {
+            name: "printed_card".to_string(),
+            length: Some(ONECARD_ID_LEN + 2),
+            steps: vec![
+                NormalizeStep::CheckDigit {
+                    algorithm: CheckAlgorithm::Mod10,
+                    keep: false,
+                },
+                NormalizeStep::DropLeading { count: 1 },
+            ],
+        },
+    ]
+}
+
+/// Turn a framed barcode into the ID submitted for it using the first
+/// format that accepts it, or `None` if none does.
+pub fn barcode_id<'a>(formats: &'a [BarcodeFormat], data: &str) -> Option<(String, &'a str)> {
+    let data = data.trim();
+    formats
+        .iter()
+        .find_map(|format| Some((format.normalize(data)?, format.name.as_str())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_formats_map_printed_cards_to_the_swipe_id() {
+        let formats = default_formats();
+        assert_eq!(
+            barcode_id(&formats, "1234567"),
+            Some(("1234567".to_string(), "onecard"))
+        );
+        // Issue code 3, ID 1234567, Mod 10 check digit 7
+        assert_eq!(
+            barcode_id(&formats, " 312345677 "),
+            Some(("1234567".to_string(), "printed_card"))
+        );
+        assert_eq!(barcode_id(&formats, "312345672"), None);
+        assert_eq!(barcode_id(&formats, "1234567890"), None);
+        assert_eq!(barcode_id(&formats, "A123456"), None);
+    }
+
+    #[test]
+    fn configured_steps_strip_validate_and_pad() {
+        let formats: Vec<BarcodeFormat> = serde_json::from_str(
+            r#"[{ "name": "library", "steps": [
+                    { "kind": "strip_prefix", "prefix": "LIB-" },
+                    { "kind": "strip_suffix", "suffix": "/X" },
+                    { "kind": "check_digit", "algorithm": "luhn" },
+                    { "kind": "zero_pad", "len": 7 } ] },
+                { "name": "code39", "steps": [
+                    { "kind": "check_digit", "algorithm": "mod43" },
+                    { "kind": "drop_trailing", "count": 1 } ] }]"#,
+        )
+        .unwrap();
+
+        // Luhn check digit of 12345 is 5
+        assert_eq!(
+            barcode_id(&formats, "LIB-123455/X"),
+            Some(("0012345".to_string(), "library"))
+        );
+        assert_eq!(barcode_id(&formats, "LIB-123454/X"), None);
+        // Mod 43 check character of 12345671 is "T"
+        assert_eq!(
+            barcode_id(&formats, "12345671T"),
+            Some(("1234567".to_string(), "code39"))
+        );
+        assert_eq!(CheckAlgorithm::Mod10.check_char("1234567"), Some('0'));
+    }
+}
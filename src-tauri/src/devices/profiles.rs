@@ -0,0 +1,307 @@
+use crate::devices::magtek::CardData;
+use crate::devices::tracks::{parse_swipe, CardSwipe};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Name of the built-in profile for the campus OneCard layout.
+pub const ONECARD_PROFILE: &str = "onecard";
+
+/// Part of a swipe a profile reads the ID from.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SwipeField {
+    /// Everything the reader sent, sentinels included.
+    Raw,
+    /// Account number from track 1, or track 2 if track 1 is unreadable.
+    Pan,
+    /// Track 1 account number field with its padding, e.g. `1234567   `.
+    Track1PanField,
+    Track1Discretionary,
+    Track2Discretionary,
+    Track3,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProfileRule {
+    /// The `id` capture group (or the first group, or the whole match) is the
+    /// ID; an optional `name` group overrides the track 1 name.
+    Regex { field: SwipeField, pattern: String },
+    /// `len` characters starting at character `start`.
+    Offset {
+        field: SwipeField,
+        start: usize,
+        len: usize,
+    },
+}
+
+/// A card layout defined in `Config::card_profiles`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProfileDefinition {
+    pub name: String,
+    #[serde(flatten)]
+    pub rule: ProfileRule,
+}
+
+/// An entry of `Config::card_profiles`: a built-in profile by name or a full definition.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ProfileEntry {
+    Builtin(String),
+    Custom(ProfileDefinition),
+}
+
+fn builtin(name: &str) -> Option<ProfileDefinition> {
+    match name {
+        // 7 digits padded with 3 spaces in track 1, e.g. `%B1234567   ^DOE/JANE^...`.
+        // Bank cards have no padding, so their account numbers never match.
+        ONECARD_PROFILE => Some(ProfileDefinition {
+            name: ONECARD_PROFILE.to_string(),
+            rule: ProfileRule::Regex {
+                field: SwipeField::Track1PanField,
+                pattern: r"(\d{7})\s{3}".to_string(),
+            },
+        }),
+        _ => None,
+    }
+}
+
+enum Matcher {
+    Regex(Regex),
+    Offset { start: usize, len: usize },
+}
+
+/// A profile ready to be applied to swipes.
+pub struct CardProfile {
+    pub name: String,
+    field: SwipeField,
+    matcher: Matcher,
+}
+
+impl CardProfile {
+    pub fn compile(definition: &ProfileDefinition) -> Result<Self, regex::Error> {
+        let (field, matcher) = match &definition.rule {
+            ProfileRule::Regex { field, pattern } => (*field, Matcher::Regex(Regex::new(pattern)?)),
+            ProfileRule::Offset { field, start, len } => (
+                *field,
+                Matcher::Offset {
+                    start: *start,
+                    len: *len,
+                },
+            ),
+        };
+        Ok(Self {
+            name: definition.name.clone(),
+            field,
+            matcher,
+        })
+    }
+
+    fn field<'a>(&self, swipe: &'a CardSwipe, raw: &'a str) -> Option<&'a str> {
+        match self.field {
+            SwipeField::Raw => Some(raw),
+            SwipeField::Pan => swipe.pan(),
+            SwipeField::Track1PanField => swipe.track1.as_ref().map(|t| t.pan_field.as_str()),
+            SwipeField::Track1Discretionary => {
+                swipe.track1.as_ref().map(|t| t.discretionary.as_str())
+            }
+            SwipeField::Track2Discretionary => {
+                swipe.track2.as_ref().map(|t| t.discretionary.as_str())
+            }
+            SwipeField::Track3 => swipe.track3.as_deref(),
+        }
+    }
+
+    /// Map a swipe to `CardData`, or `None` if it doesn't fit this layout.
+    pub fn extract(&self, swipe: &CardSwipe, raw: &str) -> Option<CardData> {
+        let text = self.field(swipe, raw)?;
+        let (id, name) = match &self.matcher {
+            Matcher::Regex(regex) => {
+                let captures = regex.captures(text)?;
+                let id = captures
+                    .name("id")
+                    .or_else(|| captures.get(1))
+                    .or_else(|| captures.get(0))?;
+                (id.as_str(), captures.name("name").map(|m| m.as_str()))
+            }
+            Matcher::Offset { start, len } => {
+                let begin = text.char_indices().nth(*start)?.0;
+                let end = text[begin..]
+                    .char_indices()
+                    .nth(*len)
+                    .map_or(text.len(), |(i, _)| begin + i);
+                let id = &text[begin..end];
+                (id.chars().count() == *len).then_some((id, None))?
+            }
+        };
+        let id = id.trim();
+        if id.is_empty() {
+            return None;
+        }
+        let name = name
+            .or_else(|| swipe.track1.as_ref().map(|t| t.name.as_str()))
+            .unwrap_or_default();
+        Some(CardData {
+            onecard: id.to_string(),
+            name: name.trim().to_string(),
+            tracks_read: swipe.tracks_read(),
+            profile: self.name.clone(),
+            encrypted: None,
+        })
+    }
+}
+
+fn resolve(entry: &ProfileEntry) -> Option<ProfileDefinition> {
+    match entry {
+        ProfileEntry::Custom(definition) => Some(definition.clone()),
+        ProfileEntry::Builtin(name) => {
+            let definition = builtin(name);
+            if definition.is_none() {
+                log::warn!("Unknown card profile {:?}", name);
+            }
+            definition
+        }
+    }
+}
+
+/// Compile the configured profiles in order. With `only`, just that profile
+/// is used, looked up in the configured definitions and then the built-ins.
+pub fn load_profiles(entries: &[ProfileEntry], only: Option<&str>) -> Vec<CardProfile> {
+    let definitions: Vec<ProfileDefinition> = match only {
+        Some(name) => entries
+            .iter()
+            .find_map(|entry| match entry {
+                ProfileEntry::Custom(definition) if definition.name == name => {
+                    Some(definition.clone())
+                }
+                _ => None,
+            })
+            .or_else(|| resolve(&ProfileEntry::Builtin(name.to_string())))
+            .into_iter()
+            .collect(),
+        None => entries.iter().filter_map(resolve).collect(),
+    };
+    definitions
+        .iter()
+        .filter_map(|definition| match CardProfile::compile(definition) {
+            Ok(profile) => Some(profile),
+            Err(e) => {
+                log::warn!("Invalid card profile {:?}: {}", definition.name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parse a swipe and return the data from the first profile that matches.
+pub fn parse_card_data(profiles: &[CardProfile], raw: &str) -> Option<CardData> {
+    let swipe = parse_swipe(raw);
+    profiles
+        .iter()
+        .find_map(|profile| profile.extract(&swipe, raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn onecard() -> Vec<CardProfile> {
+        load_profiles(&[ProfileEntry::Builtin(ONECARD_PROFILE.into())], None)
+    }
+
+    #[test]
+    fn onecard_reads_the_padded_track1_field() {
+        let data = "%B1234567   ^DOE/JOHN^1234567890123456?";
+        let card = parse_card_data(&onecard(), data).expect("Should parse");
+        assert_eq!(card.onecard, "1234567");
+        assert_eq!(card.name, "DOE/JOHN");
+        assert_eq!(card.profile, ONECARD_PROFILE);
+        assert_eq!(card.tracks_read, vec![1]);
+
+        assert!(parse_card_data(&onecard(), "%B1234567^DOE/JOHN^^^?").is_none());
+        assert!(parse_card_data(&onecard(), "%B12^BAD^").is_none());
+    }
+
+    #[test]
+    fn onecard_reads_cards_without_additional_data() {
+        let card = parse_card_data(&onecard(), "%B1234567   ^DOE/JOHN^?").expect("Should parse");
+        assert_eq!(card.onecard, "1234567");
+        assert_eq!(card.name, "DOE/JOHN");
+        // Longer than ISO 7813 allows, as some campus cards are
+        let long_name = format!("%B1234567   ^{}^?", "A".repeat(40));
+        assert!(parse_card_data(&onecard(), &long_name).is_some());
+    }
+
+    #[test]
+    fn onecard_rejects_bank_cards() {
+        let bank_card = "%B6011000012345678^DOE/JANE^2512101123456789?\
+                         ;6011000012345678=25121011234567?";
+        assert!(parse_card_data(&onecard(), bank_card).is_none());
+        // Without track 1 there is no padding to recognise a OneCard by
+        assert!(parse_card_data(&onecard(), "%E?;6011000012345678=2512101?").is_none());
+    }
+
+    #[test]
+    fn custom_profile_can_fall_back_to_track2() {
+        let entries: Vec<ProfileEntry> = serde_json::from_str(
+            r#"[{ "name": "short_pan", "kind": "regex", "field": "pan",
+                  "pattern": "^(\\d{7})$" }]"#,
+        )
+        .unwrap();
+        let card = parse_card_data(&load_profiles(&entries, None), "%E?;1234567=25121011234?")
+            .expect("Should parse track 2");
+        assert_eq!(card.onecard, "1234567");
+        assert_eq!(card.name, "");
+        assert_eq!(card.tracks_read, vec![2]);
+    }
+
+    #[test]
+    fn offset_profile_reads_track2_discretionary_data() {
+        let entries: Vec<ProfileEntry> = serde_json::from_str(
+            r#"[{ "name": "partner", "kind": "offset", "field": "track2_discretionary",
+                  "start": 4, "len": 8 }]"#,
+        )
+        .unwrap();
+        let profiles = load_profiles(&entries, None);
+
+        let card = parse_card_data(&profiles, ";6009990000000001=4912101000012345678?")
+            .expect("Should parse");
+        assert_eq!(card.onecard, "12345678");
+        assert_eq!(card.profile, "partner");
+        assert!(parse_card_data(&profiles, ";6009990000000001=4912101?").is_none());
+    }
+
+    #[test]
+    fn regex_profile_uses_named_groups() {
+        let entries: Vec<ProfileEntry> = serde_json::from_str(
+            r#"[{ "name": "visitor", "kind": "regex", "field": "raw",
+                  "pattern": "V(?P<id>\\d{6})\\^(?P<name>[^^]+)\\^" }]"#,
+        )
+        .unwrap();
+        let profiles = load_profiles(&entries, None);
+
+        let card = parse_card_data(&profiles, "%BV123456^GUEST/PAT^?").expect("Should parse");
+        assert_eq!(card.onecard, "123456");
+        assert_eq!(card.name, "GUEST/PAT");
+    }
+
+    #[test]
+    fn profiles_are_tried_in_order_and_can_be_restricted() {
+        let entries: Vec<ProfileEntry> = serde_json::from_str(
+            r#"[{ "name": "partner", "kind": "offset", "field": "track2_discretionary",
+                  "start": 0, "len": 7 },
+                "onecard", "no_such_profile"]"#,
+        )
+        .unwrap();
+        let data = "%B1234567   ^DOE/JOHN^2512101?;1234567=25121019876543?";
+
+        let card = parse_card_data(&load_profiles(&entries, None), data).unwrap();
+        assert_eq!(card.profile, "partner");
+        assert_eq!(card.onecard, "9876543");
+
+        let only_onecard = load_profiles(&entries, Some(ONECARD_PROFILE));
+        assert_eq!(only_onecard.len(), 1);
+        let card = parse_card_data(&only_onecard, data).unwrap();
+        assert_eq!(card.onecard, "1234567");
+    }
+}
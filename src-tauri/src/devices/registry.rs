@@ -0,0 +1,329 @@
+use crate::devices::contactless::IdFile;
+use crate::devices::hid_pos::HID_POS_USAGE_PAGE;
+use crate::devices::keyboard::KeyboardLayout;
+use crate::devices::serial::SerialSettings;
+use hidapi::{DeviceInfo, HidApi};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ffi::CString;
+
+/// What a reader is used for, which decides the listener it gets.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReaderRole {
+    Swipe,
+    Barcode,
+    /// A contactless (NFC) card reader.
+    Contactless,
+}
+
+/// How a reader's device is opened.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// The hidraw interface, through hidapi.
+    #[default]
+    Hid,
+    /// The Linux input event node of a keyboard-wedge reader, grabbed so its
+    /// keystrokes don't reach the desktop. Read as `boot_keyboard` reports.
+    Evdev,
+    /// A serial port (USB CDC-ACM, USB-COM or RS-232), read line by line.
+    Serial,
+    /// A PC/SC smart card reader, through the system's PC/SC service.
+    Pcsc,
+    /// A capture file from `capture_readers` at `device_path`, played back
+    /// with the registry entry it was recorded with.
+    Replay,
+}
+
+/// Layout of the input reports a reader sends.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// ASCII text, as sent by keyboard-emulation firmware.
+    #[default]
+    Text,
+    /// Binary MagneSafe report from a MagTek encrypting reader.
+    Magnesafe,
+    /// HID boot-keyboard reports from a scanner in keyboard-wedge mode.
+    BootKeyboard,
+    /// Scanned Data Reports from a HID Point-of-Sale scanner (usage page 0x8C).
+    /// Used automatically for `text` readers opened on a POS interface.
+    HidPos,
+}
+
+/// One entry of the reader registry in `Config::readers`.
+///
+/// Unset fields match any HID device. IDs may be written as numbers or as
+/// hex strings (`"0x0801"`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReaderMatch {
+    /// Label used in logs, e.g. "MagTek Dynamag".
+    #[serde(default)]
+    pub name: Option<String>,
+    pub role: ReaderRole,
+    #[serde(default, with = "hex_id")]
+    pub vendor_id: Option<u16>,
+    #[serde(default, with = "hex_id")]
+    pub product_id: Option<u16>,
+    #[serde(default, with = "hex_id")]
+    pub usage_page: Option<u16>,
+    #[serde(default)]
+    pub interface_number: Option<i32>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    /// Parser profile for data read from this device.
+    #[serde(default)]
+    pub parser_profile: Option<String>,
+    #[serde(default)]
+    pub report_format: ReportFormat,
+    /// Layout a `boot_keyboard` reader is programmed for.
+    #[serde(default)]
+    pub keyboard_layout: KeyboardLayout,
+    #[serde(default)]
+    pub source: SourceKind,
+    /// Device node for non-HID sources, e.g. `/dev/input/zebra_scanner`, or
+    /// the capture file of a `replay` source. Required for `evdev` readers;
+    /// `serial` ports are found by vendor and product ID when unset.
+    #[serde(default)]
+    pub device_path: Option<String>,
+    /// Port settings of a `serial` reader; 9600 8N1 with CR line ends when unset.
+    #[serde(default)]
+    pub serial: Option<SerialSettings>,
+    /// Start of the PC/SC name of a `pcsc` reader, e.g. "ACS ACR122U". Any
+    /// PC/SC reader matches when unset.
+    #[serde(default)]
+    pub pcsc_reader: Option<String>,
+    /// File on the card holding the OneCard ID, read from a `pcsc` reader.
+    /// Taps are not submitted when unset.
+    #[serde(default)]
+    pub id_file: Option<IdFile>,
+}
+
+/// The identifying fields of an enumerated HID device.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceIdentity {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub usage_page: u16,
+    pub interface_number: i32,
+    pub serial_number: Option<String>,
+}
+
+impl From<&DeviceInfo> for DeviceIdentity {
+    fn from(device: &DeviceInfo) -> Self {
+        Self {
+            vendor_id: device.vendor_id(),
+            product_id: device.product_id(),
+            usage_page: device.usage_page(),
+            interface_number: device.interface_number(),
+            serial_number: device.serial_number().map(str::to_string),
+        }
+    }
+}
+
+impl ReaderMatch {
+    /// Whether this is a `hid` entry matching the enumerated HID device.
+    /// Entries for other sources never match one.
+    pub fn matches(&self, device: &DeviceIdentity) -> bool {
+        self.source == SourceKind::Hid
+            && self.vendor_id.is_none_or(|id| id == device.vendor_id)
+            && self.product_id.is_none_or(|id| id == device.product_id)
+            && self.usage_page.is_none_or(|page| page == device.usage_page)
+            && self
+                .interface_number
+                .is_none_or(|n| n == device.interface_number)
+            && self
+                .serial_number
+                .as_deref()
+                .is_none_or(|s| device.serial_number.as_deref() == Some(s))
+    }
+
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!(
+                "{:04x}:{:04x}",
+                self.vendor_id.unwrap_or_default(),
+                self.product_id.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Readers used when `Config::readers` is not set: the MagTek swipe reader
+/// and the Symbol/Zebra barcode scanner the kiosks shipped with. Contactless
+/// readers need an `id_file`, so they are only used when configured.
+pub fn default_registry() -> Vec<ReaderMatch> {
+    vec![
+        ReaderMatch {
+            name: Some("MagTek card reader".to_string()),
+            role: ReaderRole::Swipe,
+            vendor_id: Some(0x0801),
+            product_id: None,
+            usage_page: None,
+            interface_number: None,
+            serial_number: None,
+            parser_profile: None,
+            report_format: ReportFormat::Text,
+            keyboard_layout: KeyboardLayout::Us,
+            source: SourceKind::Hid,
+            device_path: None,
+            serial: None,
+            pcsc_reader: None,
+            id_file: None,
+        },
+        ReaderMatch {
+            name: Some("Symbol/Zebra barcode scanner".to_string()),
+            role: ReaderRole::Barcode,
+            vendor_id: Some(0x05e0),
+            product_id: None,
+            usage_page: None,
+            interface_number: None,
+            serial_number: None,
+            parser_profile: None,
+            report_format: ReportFormat::Text,
+            keyboard_layout: KeyboardLayout::Us,
+            source: SourceKind::Hid,
+            device_path: None,
+            serial: None,
+            pcsc_reader: None,
+            id_file: None,
+        },
+    ]
+}
+
+/// First enumerated device with the given role, trying registry entries in
+/// order. A scanner's HID POS interface is preferred over its other interfaces.
+pub fn find_reader<'a>(
+    registry: &'a [ReaderMatch],
+    role: ReaderRole,
+    devices: &'a [DeviceInfo],
+) -> Option<(&'a ReaderMatch, &'a DeviceInfo)> {
+    registry
+        .iter()
+        .filter(|entry| entry.role == role && entry.source == SourceKind::Hid)
+        .find_map(|entry| {
+            let mut matching = devices
+                .iter()
+                .filter(|d| entry.matches(&DeviceIdentity::from(*d)));
+            let first = matching.next()?;
+            let device = if role == ReaderRole::Barcode && first.usage_page() != HID_POS_USAGE_PAGE
+            {
+                matching
+                    .find(|d| d.usage_page() == HID_POS_USAGE_PAGE)
+                    .unwrap_or(first)
+            } else {
+                first
+            };
+            Some((entry, device))
+        })
+}
+
+/// Find the first device with the given role. Returns its path and the
+/// registry entry, adjusted to the interface that was found.
+pub fn locate_reader(
+    api: &HidApi,
+    registry: &[ReaderMatch],
+    role: ReaderRole,
+) -> Option<(CString, ReaderMatch)> {
+    let devices: Vec<DeviceInfo> = api.device_list().cloned().collect();
+    let (entry, device) = find_reader(registry, role, &devices)?;
+    let mut entry = entry.clone();
+    if device.usage_page() == HID_POS_USAGE_PAGE && entry.report_format == ReportFormat::Text {
+        log::info!("{} is in HID POS mode", entry.label());
+        entry.report_format = ReportFormat::HidPos;
+    }
+    Some((device.path().to_owned(), entry))
+}
+
+/// (De)serialize `Option<u16>` IDs as `"0x0801"`, accepting plain numbers too.
+mod hex_id {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u16),
+        Text(String),
+    }
+
+    pub fn serialize<S: Serializer>(id: &Option<u16>, serializer: S) -> Result<S::Ok, S::Error> {
+        match id {
+            Some(id) => serializer.serialize_str(&format!("0x{:04x}", id)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u16>, D::Error> {
+        match Option::<Id>::deserialize(deserializer)? {
+            None => Ok(None),
+            Some(Id::Number(id)) => Ok(Some(id)),
+            Some(Id::Text(text)) => {
+                let trimmed = text.trim();
+                let parsed = match trimmed
+                    .strip_prefix("0x")
+                    .or_else(|| trimmed.strip_prefix("0X"))
+                {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => trimmed.parse(),
+                };
+                parsed
+                    .map(Some)
+                    .map_err(|_| serde::de::Error::custom(format!("invalid USB id {:?}", text)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_ids_and_matches_on_set_fields_only() {
+        let entry: ReaderMatch = serde_json::from_str(
+            r#"{ "role": "barcode", "vendor_id": "0x0c2e", "product_id": 2823,
+                 "interface_number": 0, "parser_profile": "onecard" }"#,
+        )
+        .unwrap();
+        assert_eq!(entry.vendor_id, Some(0x0c2e));
+        assert_eq!(entry.product_id, Some(2823));
+        assert_eq!(entry.usage_page, None);
+
+        let mut device = DeviceIdentity {
+            vendor_id: 0x0c2e,
+            product_id: 2823,
+            usage_page: 0x8c,
+            interface_number: 0,
+            serial_number: Some("A1".into()),
+        };
+        assert!(entry.matches(&device));
+        device.interface_number = 1;
+        assert!(!entry.matches(&device));
+
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["vendor_id"], "0x0c2e");
+        assert!(serde_json::from_str::<ReaderMatch>(
+            r#"{ "role": "swipe", "vendor_id": "magtek" }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn evdev_entries_never_match_hid_devices() {
+        let entry: ReaderMatch = serde_json::from_str(
+            r#"{ "role": "barcode", "source": "evdev", "device_path": "/dev/input/zebra_scanner" }"#,
+        )
+        .unwrap();
+        let keyboard = DeviceIdentity {
+            vendor_id: 0x046d,
+            product_id: 0xc31c,
+            usage_page: 0x01,
+            interface_number: 0,
+            serial_number: None,
+        };
+        assert!(!entry.matches(&keyboard));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::devices::framing::Terminator;
+use crate::devices::registry::ReaderMatch;
+use crate::devices::source::{InputSource, SourceHealth};
+use crate::error::GuestbookError;
+use serde::{Deserialize, Serialize};
+use serialport::{
+    DataBits, SerialPort, SerialPortBuilder, SerialPortInfo, SerialPortType, StopBits,
+};
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+/// Line settings of a serial reader, from `ReaderMatch::serial`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    /// Character ending each line. Lines are passed on whole, and a partial
+    /// line once the reader goes quiet.
+    pub line_end: Terminator,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            line_end: Terminator::Cr,
+        }
+    }
+}
+
+impl SerialSettings {
+    fn builder(&self, path: &str) -> Result<SerialPortBuilder, GuestbookError> {
+        let data_bits = match self.data_bits {
+            5 => DataBits::Five,
+            6 => DataBits::Six,
+            7 => DataBits::Seven,
+            8 => DataBits::Eight,
+            n => return Err(GuestbookError::Config(format!("{} data bits", n))),
+        };
+        let stop_bits = match self.stop_bits {
+            1 => StopBits::One,
+            2 => StopBits::Two,
+            n => return Err(GuestbookError::Config(format!("{} stop bits", n))),
+        };
+        let parity = match self.parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        };
+        Ok(serialport::new(path, self.baud_rate)
+            .data_bits(data_bits)
+            .parity(parity)
+            .stop_bits(stop_bits))
+    }
+}
+
+pub fn available_ports() -> Vec<SerialPortInfo> {
+    serialport::available_ports().unwrap_or_else(|e| {
+        log::warn!("Failed to list serial ports: {}", e);
+        Vec::new()
+    })
+}
+
+/// The attached port of a serial reader: its `device_path`, or the USB
+/// serial port with the entry's vendor, product and serial number. Entries
+/// without either match nothing.
+pub fn locate_port(entry: &ReaderMatch, ports: &[SerialPortInfo]) -> Option<SerialPortInfo> {
+    if let Some(path) = &entry.device_path {
+        // Windows port names such as COM3 are not paths
+        let listed = ports.iter().find(|port| &port.port_name == path);
+        return match listed {
+            Some(port) => Some(port.clone()),
+            None => Path::new(path).exists().then(|| SerialPortInfo {
+                port_name: path.clone(),
+                port_type: SerialPortType::Unknown,
+            }),
+        };
+    }
+    entry.vendor_id?;
+    ports
+        .iter()
+        .find(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => {
+                entry.vendor_id.is_none_or(|id| id == usb.vid)
+                    && entry.product_id.is_none_or(|id| id == usb.pid)
+                    && entry
+                        .serial_number
+                        .as_deref()
+                        .is_none_or(|s| usb.serial_number.as_deref() == Some(s))
+            }
+            _ => false,
+        })
+        .cloned()
+}
+
+/// A scanner or swipe reader on a serial port (USB CDC-ACM, USB-COM or
+/// RS-232). Each frame is one line.
+pub struct SerialSource {
+    path: String,
+    settings: SerialSettings,
+    port: Option<Box<dyn SerialPort>>,
+    line: Vec<u8>,
+    error: Option<String>,
+}
+
+impl SerialSource {
+    pub fn new(path: String, settings: SerialSettings) -> Self {
+        Self {
+            path,
+            settings,
+            port: None,
+            line: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn fail(&mut self, e: impl std::fmt::Display) -> GuestbookError {
+        let message = format!("{}: {}", self.path, e);
+        self.error = Some(message.clone());
+        GuestbookError::Hid(message)
+    }
+
+    /// Readers that send no line end are framed by the quiet time instead.
+    fn take_partial_line(&mut self) -> Option<Vec<u8>> {
+        (!self.line.is_empty()).then(|| std::mem::take(&mut self.line))
+    }
+}
+
+impl InputSource for SerialSource {
+    fn open(&mut self) -> Result<(), GuestbookError> {
+        // Ports are opened exclusively, so let go of an earlier handle first
+        self.close();
+        let port = self
+            .settings
+            .builder(&self.path)?
+            .open()
+            .map_err(|e| self.fail(e))?;
+        self.port = Some(port);
+        self.line.clear();
+        self.error = None;
+        Ok(())
+    }
+
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, GuestbookError> {
+        let line_end = self.settings.line_end.byte();
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0u8; 256];
+        loop {
+            if let Some(end) = self.line.iter().position(|&b| b == line_end) {
+                return Ok(Some(self.line.drain(..=end).collect()));
+            }
+            let port = self
+                .port
+                .as_mut()
+                .ok_or_else(|| GuestbookError::Hid("port is not open".to_string()))?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let read = if remaining.is_zero() {
+                Ok(0)
+            } else {
+                port.set_timeout(remaining)
+                    .map_err(io::Error::from)
+                    .and_then(|()| port.read(&mut buffer))
+            };
+            match read {
+                Ok(0) => return Ok(self.take_partial_line()),
+                Ok(size) => self.line.extend_from_slice(&buffer[..size]),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    return Ok(self.take_partial_line())
+                }
+                Err(e) => return Err(self.fail(e)),
+            }
+        }
+    }
+
+    fn health(&self) -> SourceHealth {
+        match (&self.port, &self.error) {
+            // Serial errors mean the port is gone, e.g. a USB adapter was unplugged
+            (_, Some(e)) => SourceHealth::Failed(e.clone()),
+            (None, None) => SourceHealth::Failed("port is not open".to_string()),
+            (Some(_), None) => SourceHealth::Ok,
+        }
+    }
+
+    fn close(&mut self) {
+        self.port = None;
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::config_manager::Config;
+    use crate::devices::barcode::BarcodeDecoder;
+    use crate::devices::registry::{default_registry, ReaderRole};
+    use crate::devices::test_support::start_dispatcher;
+    use crate::devices::ReaderState;
+    use std::io::Write;
+
+    #[test]
+    fn scans_from_a_pseudo_terminal_become_barcode_events() {
+        let (mut scanner, pty) = serialport::TTYPort::pair().unwrap();
+        let path = pty.name().unwrap();
+        let mut source = SerialSource::new(path.clone(), SerialSettings::default());
+        source.open().unwrap();
+        drop(pty);
+
+        // Lines are split on the line end, however they arrive
+        scanner.write_all(b"1234567\r76543").unwrap();
+        assert_eq!(
+            source.read_frame(Duration::from_secs(1)).unwrap(),
+            Some(b"1234567\r".to_vec())
+        );
+        scanner.write_all(b"21\r").unwrap();
+        assert_eq!(
+            source.read_frame(Duration::from_secs(1)).unwrap(),
+            Some(b"7654321\r".to_vec())
+        );
+        assert_eq!(source.read_frame(Duration::from_millis(50)).unwrap(), None);
+
+        let (dispatcher, emitted, listeners) = start_dispatcher();
+        let barcode = default_registry()
+            .into_iter()
+            .find(|entry| entry.role == ReaderRole::Barcode)
+            .unwrap();
+        dispatcher
+            .attach(
+                "barcode",
+                "Fake serial scanner".to_string(),
+                Box::new(source),
+                Box::new(BarcodeDecoder::new(&Config::default(), &barcode)),
+            )
+            .unwrap();
+        // No line end: the scan ends when the scanner goes quiet
+        scanner.write_all(b"2345678").unwrap();
+        let next = || emitted.recv_timeout(Duration::from_secs(5)).unwrap();
+        let (event, payload) = next();
+        assert_eq!(event, "barcode-data");
+        assert_eq!(payload["value"], "2345678");
+
+        // Hanging up is reported like an unplugged USB adapter
+        drop(scanner);
+        let (event, payload) = next();
+        assert_eq!(event, "hid-error");
+        let message = payload["message"].as_str().unwrap();
+        assert!(
+            message.contains(&format!("Fake serial scanner failed: {}: ", path)),
+            "{}",
+            message
+        );
+        assert!(matches!(
+            listeners.states().get("barcode"),
+            Some(ReaderState::Stopped(reason)) if reason.starts_with(&path)
+        ));
+    }
+}
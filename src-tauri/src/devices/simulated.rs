@@ -0,0 +1,314 @@
+use crate::config::config_manager::{get_full_config, Config, ConfigManager};
+use crate::devices::contactless::uid_frame;
+use crate::devices::registry::{ReaderMatch, ReaderRole, ReportFormat};
+use crate::devices::source::{InputSource, SourceHealth};
+use crate::error::GuestbookError;
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// Setting this to anything but `0` or an empty string turns on simulated
+/// readers, like `Config::simulated_readers`.
+pub const SIMULATED_READERS_ENV: &str = "GUESTBOOK_SIMULATED_READERS";
+/// Loopback port that accepts simulated input, one command per line.
+pub const SIMULATED_INPUT_PORT: u16 = 7531;
+
+/// Where input for each running simulated reader goes. The app shares one,
+/// `INPUTS`; tests use their own.
+#[derive(Default)]
+pub struct SimulatedInputs {
+    readers: Mutex<HashMap<&'static str, Sender<Vec<u8>>>>,
+}
+
+impl SimulatedInputs {
+    /// Route input for `reader` to a new receiver, replacing any earlier one.
+    fn connect(&self, reader: &'static str) -> Receiver<Vec<u8>> {
+        let (sender, input) = channel();
+        self.readers.lock().unwrap().insert(reader, sender);
+        input
+    }
+
+    fn send(&self, reader: &str, frame: Vec<u8>) -> Result<(), GuestbookError> {
+        let readers = self.readers.lock().unwrap();
+        readers
+            .get(reader)
+            .and_then(|input| input.send(frame).ok())
+            .ok_or_else(|| {
+                GuestbookError::Hid(format!("simulated {} reader is not running", reader))
+            })
+    }
+
+    /// Feed `data` to the running simulated reader for `input`.
+    pub fn simulate(&self, input: SimulatedInput, data: &str) -> Result<(), GuestbookError> {
+        info!("Simulated {:?}: {}", input, data);
+        self.send(input.reader(), input.frame(data))
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref INPUTS: Arc<SimulatedInputs> = Arc::new(SimulatedInputs::default());
+}
+
+pub fn is_enabled(config: &Config) -> bool {
+    config.simulated_readers()
+        || std::env::var(SIMULATED_READERS_ENV)
+            .is_ok_and(|value| !matches!(value.as_str(), "" | "0"))
+}
+
+/// Something presented at a simulated reader.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulatedInput {
+    /// Track data as a swipe reader sends it, e.g.
+    /// `%B1234567   ^DOE/JOHN^1234567890123456?`.
+    Swipe,
+    /// A barcode, without the scanner's terminator.
+    Scan,
+    /// A contactless card UID in hex, optionally followed by a space and
+    /// the text of its ID file.
+    Tap,
+}
+
+impl SimulatedInput {
+    fn reader(self) -> &'static str {
+        match self {
+            SimulatedInput::Swipe => "magtek",
+            SimulatedInput::Scan => "barcode",
+            SimulatedInput::Tap => "contactless",
+        }
+    }
+
+    /// The frame the reader's source would produce for `data`.
+    fn frame(self, data: &str) -> Vec<u8> {
+        match self {
+            SimulatedInput::Swipe => data.as_bytes().to_vec(),
+            // Scanners are usually set up to end each barcode with CR
+            SimulatedInput::Scan => {
+                format!("{}\r", data.trim_end_matches(['\r', '\n', '\t'])).into_bytes()
+            }
+            SimulatedInput::Tap => match data.split_once(' ') {
+                Some((uid, id_file)) => uid_frame(uid, Some(id_file.as_bytes())),
+                None => uid_frame(data, None),
+            },
+        }
+    }
+}
+
+/// Feed `data` to the app's running simulated reader for `input`.
+pub fn simulate(input: SimulatedInput, data: &str) -> Result<(), GuestbookError> {
+    INPUTS.simulate(input, data)
+}
+
+/// A reader that produces whatever `simulate` is given, standing in for
+/// real hardware during development.
+pub struct SimulatedSource {
+    reader: &'static str,
+    inputs: Arc<SimulatedInputs>,
+    input: Option<Receiver<Vec<u8>>>,
+}
+
+impl SimulatedSource {
+    pub fn new(reader: &'static str, inputs: Arc<SimulatedInputs>) -> Self {
+        Self {
+            reader,
+            inputs,
+            input: None,
+        }
+    }
+}
+
+impl InputSource for SimulatedSource {
+    fn open(&mut self) -> Result<(), GuestbookError> {
+        self.input = Some(self.inputs.connect(self.reader));
+        Ok(())
+    }
+
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, GuestbookError> {
+        let input = self
+            .input
+            .as_ref()
+            .ok_or_else(|| GuestbookError::Hid("reader is not open".to_string()))?;
+        match input.recv_timeout(timeout) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            // A newer simulated reader took over the input
+            Err(RecvTimeoutError::Disconnected) => {
+                self.input = None;
+                Err(GuestbookError::Hid("replaced".to_string()))
+            }
+        }
+    }
+
+    fn health(&self) -> SourceHealth {
+        match self.input {
+            Some(_) => SourceHealth::Ok,
+            None => SourceHealth::Failed("simulated reader was replaced".to_string()),
+        }
+    }
+
+    fn close(&mut self) {
+        self.input = None;
+    }
+}
+
+/// A simulated reader fed from `inputs` for the first registry entry with
+/// `role`, reading plain text so injected input needs no report encoding.
+pub fn simulated_source(
+    inputs: &Arc<SimulatedInputs>,
+    registry: &[ReaderMatch],
+    reader: &'static str,
+    role: ReaderRole,
+) -> Option<(Box<dyn InputSource>, ReaderMatch)> {
+    let mut entry = registry.iter().find(|entry| entry.role == role)?.clone();
+    entry.name = Some(format!("Simulated {}", entry.label()));
+    entry.report_format = ReportFormat::Text;
+    let source = SimulatedSource::new(reader, inputs.clone());
+    Some((Box::new(source), entry))
+}
+
+/// Parse a socket command: `swipe <tracks>`, `scan <barcode>` or
+/// `tap <uid> [<id file>]`.
+fn parse_command(line: &str) -> Result<(SimulatedInput, &str), String> {
+    let (verb, data) = line
+        .trim_end_matches(['\r', '\n'])
+        .split_once(' ')
+        .unwrap_or((line, ""));
+    let input = match verb.trim() {
+        "swipe" => SimulatedInput::Swipe,
+        "scan" => SimulatedInput::Scan,
+        "tap" => SimulatedInput::Tap,
+        other => {
+            return Err(format!(
+                "unknown command {:?}, use swipe, scan or tap",
+                other
+            ))
+        }
+    };
+    if data.is_empty() {
+        return Err(format!("{} needs data", verb));
+    }
+    Ok((input, data))
+}
+
+fn serve_connection(stream: TcpStream, inputs: &SimulatedInputs) {
+    let Ok(mut reply) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        let result = parse_command(&line)
+            .and_then(|(input, data)| inputs.simulate(input, data).map_err(|e| e.to_string()));
+        let written = match result {
+            Ok(()) => writeln!(reply, "ok"),
+            Err(e) => writeln!(reply, "error: {}", e),
+        };
+        if written.is_err() {
+            return;
+        }
+    }
+}
+
+/// When simulated readers are on, accept input on the loopback socket, e.g.
+/// `echo "scan 1234567" | nc localhost 7531`.
+pub fn start_simulated_input(app: &AppHandle) {
+    if !is_enabled(&get_full_config(app.state::<ConfigManager>())) {
+        return;
+    }
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, SIMULATED_INPUT_PORT)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Simulated input socket unavailable: {}", e);
+            return;
+        }
+    };
+    info!(
+        "Simulated readers accept input on 127.0.0.1:{}",
+        SIMULATED_INPUT_PORT
+    );
+    serve(listener, INPUTS.clone());
+}
+
+/// Answer input commands on `listener` in the background, feeding `inputs`.
+fn serve(listener: TcpListener, inputs: Arc<SimulatedInputs>) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let inputs = inputs.clone();
+            std::thread::spawn(move || serve_connection(stream, &inputs));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::registry::default_registry;
+    use crate::devices::test_support::start_dispatcher;
+    use crate::devices::READERS;
+
+    #[test]
+    fn simulated_input_goes_through_the_real_decoders() {
+        let config = Config::default();
+        let mut registry = default_registry();
+        registry.push(
+            serde_json::from_str(
+                r#"{ "role": "contactless", "source": "pcsc",
+                     "id_file": { "application": "F51230", "file": 1 } }"#,
+            )
+            .unwrap(),
+        );
+        let inputs = Arc::new(SimulatedInputs::default());
+        let (dispatcher, emitted, _) = start_dispatcher();
+        for kind in READERS.iter() {
+            let (source, entry) =
+                simulated_source(&inputs, &registry, kind.name, kind.role).unwrap();
+            let decoder = (kind.decoder)(&config, &entry);
+            dispatcher
+                .attach(kind.name, entry.label(), source, decoder)
+                .unwrap();
+        }
+        let next = || emitted.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // Through the socket, on a port of its own
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        serve(listener, inputs.clone());
+        let mut socket = TcpStream::connect(address).unwrap();
+        let mut replies = BufReader::new(socket.try_clone().unwrap()).lines();
+        writeln!(socket, "scan 1234567").unwrap();
+        assert_eq!(replies.next().unwrap().unwrap(), "ok");
+        let (event, payload) = next();
+        assert_eq!(event, "barcode-data");
+        assert_eq!(payload["value"], "1234567");
+        writeln!(socket, "beep 1").unwrap();
+        assert!(replies.next().unwrap().unwrap().starts_with("error: "));
+
+        inputs
+            .simulate(
+                SimulatedInput::Swipe,
+                "%B1234567   ^DOE/JOHN^1234567890123456?",
+            )
+            .unwrap();
+        let (event, payload) = next();
+        assert_eq!(event, "magtek-data");
+        assert_eq!(payload["onecard"], "1234567");
+
+        inputs
+            .simulate(SimulatedInput::Tap, "04a22b9100 1234567")
+            .unwrap();
+        let (event, payload) = next();
+        assert_eq!(event, "contactless-data");
+        assert_eq!(payload["onecard"], "1234567");
+        assert_eq!(payload["uid"], "04A22B9100");
+
+        assert!(parse_command("scan").is_err());
+        assert!(inputs.send("nothing", Vec::new()).is_err());
+    }
+}
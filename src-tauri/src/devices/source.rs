@@ -0,0 +1,99 @@
+use crate::error::GuestbookError;
+use hidapi::{HidApi, HidDevice};
+use std::ffi::CString;
+use std::time::Duration;
+
+/// Reads in a row that may fail before a HID device is given up on.
+const MAX_CONSECUTIVE_ERRORS: u32 = 5;
+/// Large enough for a whole MagneSafe report.
+const HID_REPORT_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceHealth {
+    Ok,
+    /// Reads are failing, but the source may still recover.
+    Degraded(String),
+    /// The source gave up and has to be reopened.
+    Failed(String),
+}
+
+/// Something that produces raw frames from a reader: a HID device, a
+/// serial port, a recording. Decoding the frames is left to the dispatcher.
+pub trait InputSource: Send {
+    /// Acquire the device. Called once before the first read.
+    fn open(&mut self) -> Result<(), GuestbookError>;
+
+    /// Wait up to `timeout` for the next frame. `Ok(None)` means nothing
+    /// arrived, which decoders use to end input sent without a terminator.
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, GuestbookError>;
+
+    fn health(&self) -> SourceHealth;
+
+    /// Release the device. No reads follow.
+    fn close(&mut self);
+}
+
+/// A reader opened through hidapi; each input report is one frame.
+pub struct HidSource {
+    path: CString,
+    device: Option<HidDevice>,
+    buffer: Vec<u8>,
+    consecutive_errors: u32,
+    last_error: Option<String>,
+}
+
+impl HidSource {
+    pub fn new(path: CString) -> Self {
+        Self {
+            path,
+            device: None,
+            buffer: vec![0; HID_REPORT_BUFFER],
+            consecutive_errors: 0,
+            last_error: None,
+        }
+    }
+}
+
+impl InputSource for HidSource {
+    fn open(&mut self) -> Result<(), GuestbookError> {
+        let api = HidApi::new()?;
+        self.device = Some(api.open_path(&self.path)?);
+        self.consecutive_errors = 0;
+        self.last_error = None;
+        Ok(())
+    }
+
+    fn read_frame(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, GuestbookError> {
+        let device = self
+            .device
+            .as_ref()
+            .ok_or_else(|| GuestbookError::Hid("device is not open".to_string()))?;
+        let timeout_ms = timeout.as_millis().try_into().unwrap_or(i32::MAX);
+        match device.read_timeout(&mut self.buffer, timeout_ms) {
+            Ok(size) => {
+                self.consecutive_errors = 0;
+                Ok((size > 0).then(|| self.buffer[..size].to_vec()))
+            }
+            Err(e) => {
+                self.consecutive_errors += 1;
+                self.last_error = Some(e.to_string());
+                Err(e.into())
+            }
+        }
+    }
+
+    fn health(&self) -> SourceHealth {
+        match &self.last_error {
+            _ if self.device.is_none() => SourceHealth::Failed("device is not open".to_string()),
+            Some(e) if self.consecutive_errors >= MAX_CONSECUTIVE_ERRORS => {
+                SourceHealth::Failed(e.clone())
+            }
+            Some(e) if self.consecutive_errors > 0 => SourceHealth::Degraded(e.clone()),
+            _ => SourceHealth::Ok,
+        }
+    }
+
+    fn close(&mut self) {
+        self.device = None;
+    }
+}
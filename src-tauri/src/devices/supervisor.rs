@@ -0,0 +1,298 @@
+use crate::config::config_manager::{get_full_config, ConfigManager};
+use crate::devices::registry::{find_reader, ReaderMatch, SourceKind};
+use crate::devices::{contactless, serial};
+use crate::devices::{reader_states, set_reader_state, start_reader, ReaderState, READERS};
+use crate::hid;
+use hidapi::DeviceInfo;
+use log::{debug, info, warn};
+use serde::Serialize;
+use serialport::{SerialPortInfo, SerialPortType};
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+/// How often the device list is re-enumerated when no hotplug event arrives.
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// Minimum time between attempts to open a reader that is attached but won't open.
+const REOPEN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Payload of the `device-connected` and `device-disconnected` events.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ReaderDevice {
+    pub reader: &'static str,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub product: Option<String>,
+}
+
+impl ReaderDevice {
+    fn new(reader: &'static str, device: &DeviceInfo) -> Self {
+        Self {
+            reader,
+            vendor_id: device.vendor_id(),
+            product_id: device.product_id(),
+            product: device.product_string().map(str::to_string),
+        }
+    }
+
+    fn serial(reader: &'static str, port: &SerialPortInfo) -> Self {
+        match &port.port_type {
+            SerialPortType::UsbPort(usb) => Self {
+                reader,
+                vendor_id: usb.vid,
+                product_id: usb.pid,
+                product: usb.product.clone(),
+            },
+            _ => Self {
+                reader,
+                vendor_id: 0,
+                product_id: 0,
+                product: Some(port.port_name.clone()),
+            },
+        }
+    }
+
+    fn event_node(reader: &'static str, path: &str) -> Self {
+        Self {
+            reader,
+            vendor_id: 0,
+            product_id: 0,
+            product: Some(path.to_string()),
+        }
+    }
+
+    fn pcsc(reader: &'static str, name: &CString) -> Self {
+        Self {
+            reader,
+            vendor_id: 0,
+            product_id: 0,
+            product: Some(name.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    Connected(ReaderDevice),
+    Disconnected(ReaderDevice),
+}
+
+/// Registered readers among the enumerated devices, present event nodes,
+/// serial ports and PC/SC readers, first match per reader kind.
+fn attached_readers(
+    registry: &[ReaderMatch],
+    devices: &[DeviceInfo],
+    ports: &[SerialPortInfo],
+    pcsc_readers: &[CString],
+) -> BTreeMap<&'static str, ReaderDevice> {
+    READERS
+        .iter()
+        .filter_map(|kind| {
+            if let Some((_, device)) = find_reader(registry, kind.role, devices) {
+                return Some((kind.name, ReaderDevice::new(kind.name, device)));
+            }
+            let entries = registry.iter().filter(|entry| entry.role == kind.role);
+            let node = entries
+                .clone()
+                .filter(|entry| entry.source == SourceKind::Evdev)
+                .filter_map(|entry| entry.device_path.as_deref())
+                .find(|path| Path::new(path).exists());
+            if let Some(path) = node {
+                return Some((kind.name, ReaderDevice::event_node(kind.name, path)));
+            }
+            let port = entries
+                .clone()
+                .filter(|entry| entry.source == SourceKind::Serial)
+                .find_map(|entry| serial::locate_port(entry, ports));
+            if let Some(port) = port {
+                return Some((kind.name, ReaderDevice::serial(kind.name, &port)));
+            }
+            let name = entries
+                .filter(|entry| entry.source == SourceKind::Pcsc)
+                .find_map(|entry| contactless::locate_pcsc_reader(entry, pcsc_readers))?;
+            Some((kind.name, ReaderDevice::pcsc(kind.name, &name)))
+        })
+        .collect()
+}
+
+fn diff(
+    before: &BTreeMap<&'static str, ReaderDevice>,
+    after: &BTreeMap<&'static str, ReaderDevice>,
+) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (reader, device) in before {
+        if after.get(reader) != Some(device) {
+            changes.push(Change::Disconnected(device.clone()));
+        }
+    }
+    for (reader, device) in after {
+        if before.get(reader) != Some(device) {
+            changes.push(Change::Connected(device.clone()));
+        }
+    }
+    changes
+}
+
+/// Wakes the supervisor when a hidraw device is added or removed. Uses a
+/// udev monitor on Linux and falls back to sleeping for the poll interval.
+struct HotplugMonitor {
+    #[cfg(target_os = "linux")]
+    socket: Option<udev::MonitorSocket>,
+}
+
+#[cfg(target_os = "linux")]
+impl HotplugMonitor {
+    fn new() -> Self {
+        let socket = udev::MonitorBuilder::new()
+            .and_then(|builder| builder.match_subsystem("hidraw"))
+            .and_then(|builder| builder.listen());
+        match socket {
+            Ok(socket) => Self {
+                socket: Some(socket),
+            },
+            Err(e) => {
+                warn!("udev monitor unavailable, polling for HID changes: {}", e);
+                Self { socket: None }
+            }
+        }
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        use std::os::unix::io::AsRawFd;
+
+        let Some(socket) = &self.socket else {
+            std::thread::sleep(timeout);
+            return;
+        };
+        let mut fds = libc::pollfd {
+            fd: socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: fds is a single valid pollfd for the duration of the call
+        let ready = unsafe { libc::poll(&mut fds, 1, timeout.as_millis() as libc::c_int) };
+        if ready < 0 {
+            // Interrupted or broken socket; don't spin
+            std::thread::sleep(timeout);
+        }
+        for event in socket.iter() {
+            debug!("udev {} {:?}", event.event_type(), event.devnode());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl HotplugMonitor {
+    fn new() -> Self {
+        Self {}
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        std::thread::sleep(timeout);
+    }
+}
+
+/// Spawn the thread that watches for readers being plugged in or out,
+/// emits `device-connected` / `device-disconnected` and reopens readers
+/// whose listener isn't running.
+pub fn start_device_supervisor(app: AppHandle) {
+    info!("Starting HID device supervisor");
+    std::thread::spawn(move || {
+        let mut monitor = HotplugMonitor::new();
+        let mut attached = BTreeMap::new();
+        let mut last_attempt: BTreeMap<&'static str, Instant> = BTreeMap::new();
+        loop {
+            // Read the registry every pass so `reload_config` applies without a restart
+            let registry = get_full_config(app.state::<ConfigManager>()).reader_registry();
+            // Serial ports and PC/SC readers are only listed when one is registered
+            let registered = |source| registry.iter().any(|entry| entry.source == source);
+            let ports = if registered(SourceKind::Serial) {
+                serial::available_ports()
+            } else {
+                Vec::new()
+            };
+            let pcsc_readers = if registered(SourceKind::Pcsc) {
+                contactless::available_readers()
+            } else {
+                Vec::new()
+            };
+            let now_attached =
+                attached_readers(&registry, &hid::list_devices(), &ports, &pcsc_readers);
+            for change in diff(&attached, &now_attached) {
+                match change {
+                    Change::Connected(device) => {
+                        info!(
+                            "{} reader connected ({:04x}:{:04x})",
+                            device.reader, device.vendor_id, device.product_id
+                        );
+                        last_attempt.remove(device.reader);
+                        app.emit("device-connected", device).ok();
+                    }
+                    Change::Disconnected(device) => {
+                        warn!(
+                            "{} reader disconnected ({:04x}:{:04x})",
+                            device.reader, device.vendor_id, device.product_id
+                        );
+                        set_reader_state(device.reader, ReaderState::NotFound);
+                        app.emit("device-disconnected", device).ok();
+                    }
+                }
+            }
+            attached = now_attached;
+
+            let states = reader_states();
+            for kind in READERS.iter().filter(|k| attached.contains_key(k.name)) {
+                if states.get(kind.name) == Some(&ReaderState::Running) {
+                    continue;
+                }
+                if last_attempt
+                    .get(kind.name)
+                    .is_some_and(|at| at.elapsed() < REOPEN_INTERVAL)
+                {
+                    continue;
+                }
+                last_attempt.insert(kind.name, Instant::now());
+                info!("Reopening {} reader", kind.name);
+                if let Err(e) = start_reader(app.clone(), kind) {
+                    warn!("Failed to reopen {} reader: {}", kind.name, e);
+                }
+            }
+            monitor.wait(POLL_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(reader: &'static str, product_id: u16) -> ReaderDevice {
+        ReaderDevice {
+            reader,
+            vendor_id: 0x0801,
+            product_id,
+            product: None,
+        }
+    }
+
+    #[test]
+    fn reports_unplugged_replugged_and_swapped_readers() {
+        let before = BTreeMap::from([
+            ("barcode", device("barcode", 1)),
+            ("magtek", device("magtek", 2)),
+        ]);
+        let after = BTreeMap::from([("magtek", device("magtek", 3))]);
+
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                Change::Disconnected(device("barcode", 1)),
+                Change::Disconnected(device("magtek", 2)),
+                Change::Connected(device("magtek", 3)),
+            ]
+        );
+        assert!(diff(&after, &after).is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+//! Fakes shared by the reader tests.
+
+use crate::devices::dispatcher::{Dispatcher, EventSink};
+use crate::devices::source::{InputSource, SourceHealth};
+use crate::devices::Listeners;
+use crate::error::GuestbookError;
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Events emitted to a test sink, as `(event, payload)`.
+pub type Emitted = Receiver<(String, serde_json::Value)>;
+
+impl EventSink for Sender<(String, serde_json::Value)> {
+    fn send(&self, event: &str, payload: serde_json::Value) {
+        Sender::send(self, (event.to_string(), payload)).ok();
+    }
+}
+
+/// A dispatcher emitting to a channel, with listener state of its own so
+/// tests don't see each other's readers.
+pub fn start_dispatcher() -> (Dispatcher, Emitted, Arc<Listeners>) {
+    let (sink, emitted) = channel();
+    let listeners = Arc::new(Listeners::default());
+    let dispatcher = Dispatcher::with_listeners(sink, listeners.clone());
+    (dispatcher, emitted, listeners)
+}
+
+/// Plays back frames, with `None` for a read that timed out, then fails as
+/// if unplugged.
+pub struct FakeSource {
+    frames: VecDeque<Option<Vec<u8>>>,
+    /// Pause before each read, like a real reader waiting for input.
+    delay: Duration,
+}
+
+impl FakeSource {
+    pub fn new(frames: Vec<Option<&[u8]>>) -> Self {
+        Self {
+            frames: frames.into_iter().map(|f| f.map(<[u8]>::to_vec)).collect(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn boxed(frames: Vec<Option<&[u8]>>) -> Box<dyn InputSource> {
+        Box::new(Self::new(frames))
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+impl InputSource for FakeSource {
+    fn open(&mut self) -> Result<(), GuestbookError> {
+        Ok(())
+    }
+
+    fn read_frame(&mut self, _: Duration) -> Result<Option<Vec<u8>>, GuestbookError> {
+        std::thread::sleep(self.delay);
+        match self.frames.pop_front() {
+            Some(frame) => Ok(frame),
+            None => Err(GuestbookError::Hid("unplugged".to_string())),
+        }
+    }
+
+    fn health(&self) -> SourceHealth {
+        if self.frames.is_empty() {
+            SourceHealth::Failed("unplugged".to_string())
+        } else {
+            SourceHealth::Ok
+        }
+    }
+
+    fn close(&mut self) {}
+}
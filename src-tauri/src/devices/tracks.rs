@@ -0,0 +1,299 @@
+use serde::Serialize;
+
+/// Longest primary account number allowed by ISO 7813, enforced on track 2.
+const MAX_PAN_LEN: usize = 19;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackStatus {
+    /// The reader sent nothing for this track.
+    Missing,
+    Read,
+    /// The reader reported an error, the LRC didn't match or the layout is wrong.
+    Invalid,
+}
+
+/// Track 1 (IATA, alphanumeric): `%B<PAN>^<NAME>^<YYMM><SSS><discretionary>?`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Track1 {
+    pub format_code: char,
+    pub pan: String,
+    /// The account number field as encoded, including the space padding
+    /// that some layouts (like the OneCard) are recognised by.
+    pub pan_field: String,
+    pub name: String,
+    /// `YYMM`, absent when the card carries a field separator instead.
+    pub expiry: Option<String>,
+    pub service_code: Option<String>,
+    pub discretionary: String,
+}
+
+/// Track 2 (ABA, numeric): `;<PAN>=<YYMM><SSS><discretionary>?`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Track2 {
+    pub pan: String,
+    pub expiry: Option<String>,
+    pub service_code: Option<String>,
+    pub discretionary: String,
+}
+
+/// Everything read from one swipe. `status` is indexed by track number - 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CardSwipe {
+    pub track1: Option<Track1>,
+    pub track2: Option<Track2>,
+    /// Track 3 has no common layout, so it is kept as the raw data.
+    pub track3: Option<String>,
+    pub status: [TrackStatus; 3],
+}
+
+impl CardSwipe {
+    /// Numbers of the tracks that were read successfully.
+    pub fn tracks_read(&self) -> Vec<u8> {
+        (1..=3)
+            .filter(|&n| self.status[n as usize - 1] == TrackStatus::Read)
+            .collect()
+    }
+
+    /// Account number from track 1, or from track 2 if track 1 is unusable.
+    pub fn pan(&self) -> Option<&str> {
+        self.track1
+            .as_ref()
+            .map(|t| t.pan.as_str())
+            .or_else(|| self.track2.as_ref().map(|t| t.pan.as_str()))
+    }
+}
+
+/// Character encoding of a track, which decides its LRC arithmetic.
+#[derive(Clone, Copy)]
+enum Encoding {
+    /// 6-bit characters, ASCII 0x20..=0x5F.
+    Alpha,
+    /// 4-bit characters, ASCII 0x30..=0x3F.
+    Numeric,
+}
+
+impl Encoding {
+    fn value(self, c: char) -> Option<u8> {
+        let (base, mask) = match self {
+            Encoding::Alpha => (0x20, 0x3f),
+            Encoding::Numeric => (0x30, 0x0f),
+        };
+        let code = u32::from(c);
+        (base..=base + mask)
+            .contains(&code)
+            .then(|| (code - base) as u8)
+    }
+
+    /// LRC character over the sentinels and data, as the encoder writes it.
+    fn lrc(self, start: char, body: &str) -> Option<char> {
+        let mut lrc = 0u8;
+        for c in std::iter::once(start).chain(body.chars()).chain(['?']) {
+            lrc ^= self.value(c)?;
+        }
+        let base = match self {
+            Encoding::Alpha => 0x20,
+            Encoding::Numeric => 0x30,
+        };
+        char::from_u32(u32::from(lrc) + base)
+    }
+}
+
+/// Split raw reader output into tracks by their sentinels and parse each.
+///
+/// Track 1 starts with `%`; track 2 with `;`; track 3 with `;` (the second one)
+/// or `+`. A character directly after the end sentinel `?` is treated as the
+/// LRC and checked. MagTek readers send `E` as the body of an unreadable track.
+pub fn parse_swipe(raw: &str) -> CardSwipe {
+    let mut swipe = CardSwipe {
+        track1: None,
+        track2: None,
+        track3: None,
+        status: [TrackStatus::Missing; 3],
+    };
+    let mut rest = raw;
+    while let Some(start_idx) = rest.find(['%', ';', '+']) {
+        let start = rest[start_idx..].chars().next().unwrap_or_default();
+        let after_start = &rest[start_idx + 1..];
+        let track = match start {
+            '%' => 1,
+            ';' if swipe.status[1] == TrackStatus::Missing => 2,
+            _ => 3,
+        };
+        let Some(end) = after_start.find('?') else {
+            swipe.status[track - 1] = TrackStatus::Invalid;
+            break;
+        };
+        let body = &after_start[..end];
+        rest = &after_start[end + 1..];
+
+        let encoding = if track == 1 {
+            Encoding::Alpha
+        } else {
+            Encoding::Numeric
+        };
+        let mut lrc_ok = true;
+        if let Some(lrc) = rest
+            .chars()
+            .next()
+            .filter(|c| !matches!(c, '%' | ';' | '+') && !c.is_whitespace())
+        {
+            if encoding.value(lrc).is_some() {
+                // Track 3 may use '+' as its start sentinel; the LRC is computed over ';'
+                let sentinel = if start == '+' { ';' } else { start };
+                lrc_ok = encoding.lrc(sentinel, body) == Some(lrc);
+                rest = &rest[lrc.len_utf8()..];
+            }
+        }
+
+        let status = if !lrc_ok || body == "E" {
+            TrackStatus::Invalid
+        } else {
+            match track {
+                1 => parse_track1(body).map(|t| swipe.track1 = Some(t)),
+                2 => parse_track2(body).map(|t| swipe.track2 = Some(t)),
+                _ => {
+                    swipe.track3 = Some(body.to_string());
+                    Some(())
+                }
+            }
+            .map_or(TrackStatus::Invalid, |()| TrackStatus::Read)
+        };
+        swipe.status[track - 1] = status;
+    }
+    swipe
+}
+
+/// Split `<YYMM><SSS><discretionary>`, where a leading separator stands in
+/// for an absent expiry or service code. Campus cards often end the track
+/// early, so a field the remaining data is too short for is absent too.
+fn split_additional_data(
+    data: &str,
+    separator: char,
+) -> Option<(Option<String>, Option<String>, String)> {
+    fn field(data: &str, len: usize, separator: char) -> Option<(Option<String>, &str)> {
+        if let Some(rest) = data.strip_prefix(separator) {
+            return Some((None, rest));
+        }
+        let Some(value) = data.get(..len) else {
+            return Some((None, data));
+        };
+        value
+            .chars()
+            .all(|c| c.is_ascii_digit())
+            .then(|| (Some(value.to_string()), &data[len..]))
+    }
+    let (expiry, rest) = field(data, 4, separator)?;
+    let (service_code, rest) = field(rest, 3, separator)?;
+    Some((expiry, service_code, rest.to_string()))
+}
+
+fn valid_pan(pan: &str, allow_spaces: bool) -> bool {
+    let digits = pan.trim_end();
+    !digits.is_empty()
+        && digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || (allow_spaces && c == ' '))
+}
+
+fn parse_track1(body: &str) -> Option<Track1> {
+    let mut chars = body.chars();
+    let format_code = chars.next().filter(|c| c.is_ascii_uppercase())?;
+    let mut fields = chars.as_str().splitn(3, '^');
+    let pan = fields.next()?;
+    let name = fields.next()?;
+    let additional = fields.next()?;
+    // Not held to the ISO PAN and name lengths, which campus cards exceed
+    if !valid_pan(pan, true) {
+        return None;
+    }
+    let (expiry, service_code, discretionary) = split_additional_data(additional, '^')?;
+    Some(Track1 {
+        format_code,
+        pan: pan.trim_end().to_string(),
+        pan_field: pan.to_string(),
+        name: name.trim().to_string(),
+        expiry,
+        service_code,
+        discretionary,
+    })
+}
+
+fn parse_track2(body: &str) -> Option<Track2> {
+    let (pan, additional) = body.split_once('=')?;
+    if !valid_pan(pan, false) || pan.len() > MAX_PAN_LEN {
+        return None;
+    }
+    let (expiry, service_code, discretionary) = split_additional_data(additional, '=')?;
+    Some(Track2 {
+        pan: pan.to_string(),
+        expiry,
+        service_code,
+        discretionary,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_all_three_tracks_and_checks_lrc() {
+        let raw = "%B6011000012345678^DOE/JANE^2512101123456789?2\
+                   ;6011000012345678=25121011234567?3\
+                   ;011234567890123456789?\r";
+        let swipe = parse_swipe(raw);
+
+        assert_eq!(swipe.tracks_read(), vec![1, 2, 3]);
+        let track1 = swipe.track1.as_ref().unwrap();
+        assert_eq!(track1.format_code, 'B');
+        assert_eq!(track1.pan, "6011000012345678");
+        assert_eq!(track1.name, "DOE/JANE");
+        assert_eq!(track1.expiry.as_deref(), Some("2512"));
+        assert_eq!(track1.service_code.as_deref(), Some("101"));
+        assert_eq!(track1.discretionary, "123456789");
+        let track2 = swipe.track2.as_ref().unwrap();
+        assert_eq!(track2.expiry.as_deref(), Some("2512"));
+        assert_eq!(track2.discretionary, "1234567");
+        assert_eq!(swipe.track3.as_deref(), Some("011234567890123456789"));
+
+        let padded = parse_swipe("%B1234567   ^DOE/JOHN^^^?").track1.unwrap();
+        assert_eq!(padded.pan, "1234567");
+        assert_eq!(padded.pan_field, "1234567   ");
+
+        // Campus cards may end track 1 right after the name
+        let short = parse_swipe("%B1234567   ^DOE/JOHN^?").track1.unwrap();
+        assert_eq!(short.name, "DOE/JOHN");
+        assert_eq!((short.expiry, short.service_code), (None, None));
+        assert_eq!(short.discretionary, "");
+
+        let corrupted = parse_swipe("%B6011000012345678^DOE/JANE^2512101123456789?7");
+        assert_eq!(corrupted.status[0], TrackStatus::Invalid);
+        assert!(corrupted.track1.is_none());
+    }
+
+    #[test]
+    fn falls_back_to_track2_when_track1_is_unreadable() {
+        let swipe = parse_swipe("%E?;1234567===?+E?");
+
+        assert_eq!(
+            swipe.status,
+            [
+                TrackStatus::Invalid,
+                TrackStatus::Read,
+                TrackStatus::Invalid
+            ]
+        );
+        assert_eq!(swipe.pan(), Some("1234567"));
+        let track2 = swipe.track2.unwrap();
+        assert_eq!(track2.expiry, None);
+        assert_eq!(track2.service_code, None);
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_tracks() {
+        assert_eq!(parse_swipe("%B12^BAD^").status[0], TrackStatus::Invalid);
+        assert_eq!(parse_swipe("%BABC^DOE^?").status[0], TrackStatus::Invalid);
+        assert_eq!(parse_swipe("no sentinels").tracks_read(), Vec::<u8>::new());
+    }
+}
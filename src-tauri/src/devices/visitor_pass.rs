@@ -0,0 +1,406 @@
+use crate::config::config_manager::Config;
+use crate::error::GuestbookError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Allowed difference between the kiosk clock and the pass issuer's.
+const CLOCK_SKEW_SECS: i64 = 60;
+
+/// A visitor pass read from a QR code, after its signature and validity
+/// window were checked.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct VisitorPass {
+    pub pass_id: String,
+    pub name: String,
+    /// RFC 3339 timestamps.
+    pub valid_from: String,
+    pub valid_until: String,
+}
+
+/// Payload of the `visitor-pass` event. The webview submits `token`, the
+/// barcode as scanned, which is verified again before it is recorded.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ScannedPass {
+    #[serde(flatten)]
+    pub pass: VisitorPass,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassRejection {
+    Malformed(String),
+    /// No `visitor_pass_public_key` is configured to verify passes with.
+    NoKey,
+    BadSignature,
+    NotYetValid,
+    Expired,
+}
+
+impl fmt::Display for PassRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassRejection::Malformed(e) => write!(f, "malformed visitor pass: {}", e),
+            PassRejection::NoKey => write!(f, "no visitor pass key configured"),
+            PassRejection::BadSignature => write!(f, "visitor pass signature is invalid"),
+            PassRejection::NotYetValid => write!(f, "visitor pass is not valid yet"),
+            PassRejection::Expired => write!(f, "visitor pass has expired"),
+        }
+    }
+}
+
+fn malformed(e: impl fmt::Display) -> PassRejection {
+    PassRejection::Malformed(e.to_string())
+}
+
+/// Decode base64url, also accepting standard base64 and padding.
+fn decode_base64(text: &str) -> Result<Vec<u8>, PassRejection> {
+    let text: String = text
+        .trim_end_matches('=')
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect();
+    URL_SAFE_NO_PAD.decode(text).map_err(malformed)
+}
+
+/// Parse the Ed25519 public key from `Config::visitor_pass_public_key`.
+pub fn parse_public_key(text: &str) -> Result<VerifyingKey, PassRejection> {
+    let bytes: [u8; 32] = decode_base64(text.trim())?
+        .try_into()
+        .map_err(|_| malformed("public key must be 32 bytes"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(malformed)
+}
+
+/// The configured `visitor_pass_public_key`, if it is valid.
+pub fn configured_key(config: &Config) -> Option<VerifyingKey> {
+    let key = config.visitor_pass_public_key.as_deref()?;
+    parse_public_key(key)
+        .inspect_err(|e| log::warn!("Ignoring visitor_pass_public_key: {}", e))
+        .ok()
+}
+
+/// Verify a pass submitted by the webview, so nothing but a barcode with a
+/// valid signature is recorded as a visitor entry.
+pub fn verify_scanned_pass(config: &Config, token: &str) -> Result<VisitorPass, GuestbookError> {
+    match parse_visitor_pass(token, configured_key(config).as_ref(), Utc::now()) {
+        Some(Ok(pass)) => Ok(pass),
+        Some(Err(rejection)) => Err(GuestbookError::Parse(rejection.to_string())),
+        None => Err(GuestbookError::Parse("not a visitor pass".to_string())),
+    }
+}
+
+/// Fields of a pass and the bytes its signature covers, before verification.
+struct SignedPass {
+    pass_id: String,
+    name: String,
+    valid_from: DateTime<Utc>,
+    valid_until: DateTime<Utc>,
+    message: Vec<u8>,
+    signature: String,
+}
+
+/// JSON and URL passes sign `pass_id`, `name`, `valid_from` and
+/// `valid_until` as given, joined by newlines.
+fn from_fields(
+    pass_id: &str,
+    name: &str,
+    valid_from: &str,
+    valid_until: &str,
+    signature: &str,
+) -> Result<SignedPass, PassRejection> {
+    let timestamp = |text: &str| {
+        DateTime::parse_from_rfc3339(text)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| malformed(format!("bad timestamp {:?}: {}", text, e)))
+    };
+    Ok(SignedPass {
+        pass_id: pass_id.to_string(),
+        name: name.to_string(),
+        valid_from: timestamp(valid_from)?,
+        valid_until: timestamp(valid_until)?,
+        message: [pass_id, name, valid_from, valid_until]
+            .join("\n")
+            .into_bytes(),
+        signature: signature.to_string(),
+    })
+}
+
+#[derive(Deserialize)]
+struct JsonPass {
+    pass_id: String,
+    name: String,
+    valid_from: String,
+    valid_until: String,
+    sig: String,
+}
+
+/// Claims of a compact token (an EdDSA-signed JWT).
+#[derive(Deserialize)]
+struct TokenClaims {
+    jti: String,
+    name: String,
+    nbf: i64,
+    exp: i64,
+}
+
+fn from_token(token: &str) -> Result<SignedPass, PassRejection> {
+    let (signed, signature) = token
+        .rsplit_once('.')
+        .ok_or_else(|| malformed("no signature"))?;
+    let (_, claims) = signed
+        .split_once('.')
+        .ok_or_else(|| malformed("no claims"))?;
+    let claims: TokenClaims = serde_json::from_slice(&decode_base64(claims)?).map_err(malformed)?;
+    let timestamp =
+        |secs| DateTime::from_timestamp(secs, 0).ok_or_else(|| malformed("timestamp out of range"));
+    Ok(SignedPass {
+        pass_id: claims.jti,
+        name: claims.name,
+        valid_from: timestamp(claims.nbf)?,
+        valid_until: timestamp(claims.exp)?,
+        message: signed.as_bytes().to_vec(),
+        signature: signature.to_string(),
+    })
+}
+
+fn from_url(url: &url::Url) -> Option<Result<SignedPass, PassRejection>> {
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    // URLs without a pass ID are ordinary links, not passes
+    let pass_id = query.get("pass_id")?;
+    let field = |name: &str| {
+        query
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| malformed(format!("missing {}", name)))
+    };
+    Some(field("name").and_then(|name| {
+        from_fields(
+            pass_id,
+            name,
+            field("valid_from")?,
+            field("valid_until")?,
+            field("sig")?,
+        )
+    }))
+}
+
+/// A compact JWT: three base64url segments, the first a JSON header.
+fn is_token(data: &str) -> bool {
+    let segments: Vec<&str> = data.split('.').collect();
+    let [header, ..] = segments[..] else {
+        return false;
+    };
+    segments.len() == 3
+        && segments
+            .iter()
+            .all(|segment| !segment.is_empty() && URL_SAFE_NO_PAD.decode(segment).is_ok())
+        && URL_SAFE_NO_PAD
+            .decode(header)
+            .ok()
+            .and_then(|header| {
+                serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&header).ok()
+            })
+            .is_some_and(|header| header.contains_key("alg"))
+}
+
+/// Recognize a visitor pass in a scanned barcode. Returns `None` when the
+/// barcode isn't shaped like a pass, so it can be tried as a card ID.
+pub fn parse_visitor_pass(
+    data: &str,
+    key: Option<&VerifyingKey>,
+    now: DateTime<Utc>,
+) -> Option<Result<VisitorPass, PassRejection>> {
+    let data = data.trim();
+    let signed = if data.starts_with('{') {
+        serde_json::from_str::<JsonPass>(data)
+            .map_err(malformed)
+            .and_then(|p| from_fields(&p.pass_id, &p.name, &p.valid_from, &p.valid_until, &p.sig))
+    } else if data.starts_with("https://") || data.starts_with("http://") {
+        from_url(&url::Url::parse(data).ok()?)?
+    } else if is_token(data) {
+        from_token(data)
+    } else {
+        return None;
+    };
+    Some(signed.and_then(|pass| verify(pass, key, now)))
+}
+
+fn verify(
+    pass: SignedPass,
+    key: Option<&VerifyingKey>,
+    now: DateTime<Utc>,
+) -> Result<VisitorPass, PassRejection> {
+    let key = key.ok_or(PassRejection::NoKey)?;
+    let signature = Signature::from_slice(&decode_base64(&pass.signature)?)
+        .map_err(|_| PassRejection::BadSignature)?;
+    key.verify(&pass.message, &signature)
+        .map_err(|_| PassRejection::BadSignature)?;
+    let skew = Duration::seconds(CLOCK_SKEW_SECS);
+    if now + skew < pass.valid_from {
+        return Err(PassRejection::NotYetValid);
+    }
+    if now - skew > pass.valid_until {
+        return Err(PassRejection::Expired);
+    }
+    Ok(VisitorPass {
+        pass_id: pass.pass_id,
+        name: pass.name,
+        valid_from: pass.valid_from.to_rfc3339_opts(SecondsFormat::Secs, true),
+        valid_until: pass.valid_until.to_rfc3339_opts(SecondsFormat::Secs, true),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    fn sign(message: &str) -> String {
+        URL_SAFE_NO_PAD.encode(signing_key().sign(message.as_bytes()).to_bytes())
+    }
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn accepts_signed_json_url_and_token_passes() {
+        let key = signing_key().verifying_key();
+        let now = at("2025-03-01T12:00:00Z");
+        let fields = "V-1001\nPAT GUEST\n2025-03-01T08:00:00Z\n2025-03-01T18:00:00Z";
+        let expected = VisitorPass {
+            pass_id: "V-1001".into(),
+            name: "PAT GUEST".into(),
+            valid_from: "2025-03-01T08:00:00Z".into(),
+            valid_until: "2025-03-01T18:00:00Z".into(),
+        };
+
+        let json = format!(
+            r#"{{"pass_id":"V-1001","name":"PAT GUEST","valid_from":"2025-03-01T08:00:00Z",
+                "valid_until":"2025-03-01T18:00:00Z","sig":"{}"}}"#,
+            sign(fields)
+        );
+        assert_eq!(
+            parse_visitor_pass(&json, Some(&key), now),
+            Some(Ok(expected.clone()))
+        );
+
+        let url = format!(
+            "https://guestbook.example.edu/pass?pass_id=V-1001&name=PAT%20GUEST\
+             &valid_from=2025-03-01T08:00:00Z&valid_until=2025-03-01T18:00:00Z&sig={}",
+            sign(fields)
+        );
+        assert_eq!(
+            parse_visitor_pass(&url, Some(&key), now),
+            Some(Ok(expected.clone()))
+        );
+
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"EdDSA","typ":"JWT"}"#);
+        let claims = URL_SAFE_NO_PAD
+            .encode(r#"{"jti":"V-1001","name":"PAT GUEST","nbf":1740816000,"exp":1740852000}"#);
+        let signed = format!("{}.{}", header, claims);
+        let token = format!("{}.{}", signed, sign(&signed));
+        assert_eq!(
+            parse_visitor_pass(&token, Some(&key), now),
+            Some(Ok(expected))
+        );
+
+        // Card numbers, ordinary links and dotted barcodes aren't passes
+        assert_eq!(parse_visitor_pass("1234567", Some(&key), now), None);
+        for barcode in ["1.2.3", "ABCD.EFGH.IJKL", "abc.def.", "V1.2024.0315"] {
+            assert_eq!(parse_visitor_pass(barcode, Some(&key), now), None);
+        }
+        assert_eq!(
+            parse_visitor_pass("https://example.edu/about", Some(&key), now),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_forged_expired_and_unverifiable_passes() {
+        let key = signing_key().verifying_key();
+        let pass = |name: &str, signed_name: &str| {
+            format!(
+                r#"{{"pass_id":"V-1","name":"{}","valid_from":"2025-03-01T08:00:00Z",
+                    "valid_until":"2025-03-01T18:00:00Z","sig":"{}"}}"#,
+                name,
+                sign(&format!(
+                    "V-1\n{}\n2025-03-01T08:00:00Z\n2025-03-01T18:00:00Z",
+                    signed_name
+                ))
+            )
+        };
+        let genuine = pass("PAT", "PAT");
+        let reject =
+            |data: &str, key, now| parse_visitor_pass(data, key, at(now)).unwrap().unwrap_err();
+
+        assert_eq!(
+            reject(&pass("MALLORY", "PAT"), Some(&key), "2025-03-01T12:00:00Z"),
+            PassRejection::BadSignature
+        );
+        assert_eq!(
+            reject(&genuine, Some(&key), "2025-03-01T18:05:00Z"),
+            PassRejection::Expired
+        );
+        assert_eq!(
+            reject(&genuine, Some(&key), "2025-03-01T07:00:00Z"),
+            PassRejection::NotYetValid
+        );
+        assert_eq!(
+            reject(&genuine, None, "2025-03-01T12:00:00Z"),
+            PassRejection::NoKey
+        );
+        let other_key = SigningKey::from_bytes(&[8u8; 32]).verifying_key();
+        assert_eq!(
+            reject(&genuine, Some(&other_key), "2025-03-01T12:00:00Z"),
+            PassRejection::BadSignature
+        );
+        assert!(matches!(
+            reject("{not json", Some(&key), "2025-03-01T12:00:00Z"),
+            PassRejection::Malformed(_)
+        ));
+    }
+
+    #[test]
+    fn submitted_passes_are_verified_against_the_config() {
+        let now = Utc::now();
+        let (from, until) = (
+            (now - Duration::hours(1)).to_rfc3339_opts(SecondsFormat::Secs, true),
+            (now + Duration::hours(1)).to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+        let pass = |name: &str, sig: &str| {
+            format!(
+                r#"{{"pass_id":"V-1","name":"{}","valid_from":"{}","valid_until":"{}","sig":"{}"}}"#,
+                name, from, until, sig
+            )
+        };
+        let sig = sign(&format!("V-1\nPAT\n{}\n{}", from, until));
+        let mut config = Config::default();
+        assert!(verify_scanned_pass(&config, &pass("PAT", &sig)).is_err());
+
+        config.visitor_pass_public_key =
+            Some(URL_SAFE_NO_PAD.encode(signing_key().verifying_key().as_bytes()));
+        assert_eq!(
+            verify_scanned_pass(&config, &pass("PAT", &sig))
+                .unwrap()
+                .name,
+            "PAT"
+        );
+        assert!(verify_scanned_pass(&config, &pass("MALLORY", &sig)).is_err());
+        assert!(verify_scanned_pass(&config, "1234567").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use tauri_plugin_http::reqwest;
+
+/// Errors returned by the api/devices modules and the Tauri commands.
+///
+/// Serialized to the frontend as `{ kind, message, status? }` so
+/// `errorHandler.ts` can pick a severity from `kind` instead of the message text.
+#[derive(Debug, Clone)]
+pub enum GuestbookError {
+    /// The request could not be sent or no response was received.
+    Network(String),
+    /// The server answered with a non-success status.
+    Http { status: u16, message: String },
+    /// The server rejected the device token (401/403).
+    Unauthorized { status: u16, message: String },
+    /// The device has no server token yet.
+    NotRegistered,
+    /// Missing or unusable configuration.
+    Config(String),
+    /// A reader could not be found, opened or read.
+    Hid(String),
+    /// A response or payload could not be decoded.
+    Parse(String),
+    /// The local entry database or log files could not be read or written.
+    Storage(String),
+}
+
+impl GuestbookError {
+    /// Build the error for a non-success HTTP status.
+    pub fn from_status(status: u16, message: String) -> Self {
+        match status {
+            401 | 403 => GuestbookError::Unauthorized { status, message },
+            _ => GuestbookError::Http { status, message },
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GuestbookError::Network(_) => "network",
+            GuestbookError::Http { .. } => "http",
+            GuestbookError::Unauthorized { .. } => "unauthorized",
+            GuestbookError::NotRegistered => "not_registered",
+            GuestbookError::Config(_) => "config",
+            GuestbookError::Hid(_) => "hid",
+            GuestbookError::Parse(_) => "parse",
+            GuestbookError::Storage(_) => "storage",
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            GuestbookError::Http { status, .. } | GuestbookError::Unauthorized { status, .. } => {
+                Some(*status)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GuestbookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuestbookError::Network(e) => write!(f, "Network error: {}", e),
+            GuestbookError::Http { status, message } => {
+                write!(f, "Server returned status {}: {}", status, message)
+            }
+            GuestbookError::Unauthorized { status, message } => {
+                write!(f, "Device token rejected (status {}): {}", status, message)
+            }
+            GuestbookError::NotRegistered => {
+                write!(f, "Device is not registered (no server token)")
+            }
+            GuestbookError::Config(e) => write!(f, "Configuration error: {}", e),
+            GuestbookError::Hid(e) => write!(f, "HID error: {}", e),
+            GuestbookError::Parse(e) => write!(f, "Parse error: {}", e),
+            GuestbookError::Storage(e) => write!(f, "Storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GuestbookError {}
+
+#[derive(Serialize)]
+struct ErrorPayload {
+    kind: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+}
+
+impl Serialize for GuestbookError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorPayload {
+            kind: self.kind(),
+            message: self.to_string(),
+            status: self.status(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<reqwest::Error> for GuestbookError {
+    fn from(e: reqwest::Error) -> Self {
+        GuestbookError::Network(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for GuestbookError {
+    fn from(e: serde_json::Error) -> Self {
+        GuestbookError::Parse(e.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for GuestbookError {
+    fn from(e: rusqlite::Error) -> Self {
+        GuestbookError::Storage(e.to_string())
+    }
+}
+
+impl From<hidapi::HidError> for GuestbookError {
+    fn from(e: hidapi::HidError) -> Self {
+        GuestbookError::Hid(e.to_string())
+    }
+}
+
+impl From<pcsc::Error> for GuestbookError {
+    fn from(e: pcsc::Error) -> Self {
+        GuestbookError::Hid(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_kind_message_and_status() {
+        let value =
+            serde_json::to_value(GuestbookError::from_status(403, "bad token".into())).unwrap();
+        assert_eq!(value["kind"], "unauthorized");
+        assert_eq!(value["status"], 403);
+        assert!(value["message"].as_str().unwrap().contains("bad token"));
+
+        let value = serde_json::to_value(GuestbookError::NotRegistered).unwrap();
+        assert_eq!(value["kind"], "not_registered");
+        assert!(value.get("status").is_none());
+    }
+}
@@ -6,6 +6,10 @@ lazy_static::lazy_static! {
 }
 
 pub fn list_devices() -> Vec<DeviceInfo> {
-    let api = HID_API.lock().unwrap();
+    let mut api = HID_API.lock().unwrap();
+    // Re-enumerate so unplugged readers don't linger in the list
+    if let Err(e) = api.refresh_devices() {
+        log::warn!("Failed to refresh HID device list: {}", e);
+    }
     api.device_list().cloned().collect()
 }
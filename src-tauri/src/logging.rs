@@ -13,7 +13,7 @@ pub struct FileLogger {
 
 impl FileLogger {
     pub fn new(config_dir: &Path) -> io::Result<Self> {
-        let log_dir = config_dir.parent().unwrap().join("logs");
+        let log_dir = log_dir(config_dir);
         fs::create_dir_all(&log_dir)?;
 
         let current_date = Local::now().format("%Y-%m-%d").to_string();
@@ -27,7 +27,7 @@ impl FileLogger {
     }
 
     fn open_log_file(log_dir: &Path, date: &str) -> io::Result<Option<File>> {
-        let log_file = log_dir.join(format!("guestbook-{}.log", date));
+        let log_file = log_file(log_dir, date);
         let file = OpenOptions::new()
             .create(true)
             .append(true)
@@ -91,6 +91,16 @@ impl Log for FileLogger {
     }
 }
 
+/// Logs live in a `logs/` directory next to the config file.
+pub fn log_dir(config_path: &Path) -> PathBuf {
+    config_path.parent().unwrap().join("logs")
+}
+
+/// Daily log file for `date` (`YYYY-MM-DD`, local time).
+pub fn log_file(log_dir: &Path, date: &str) -> PathBuf {
+    log_dir.join(format!("guestbook-{}.log", date))
+}
+
 pub fn init_logging(config_dir: &Path) -> io::Result<()> {
     let file_logger = FileLogger::new(config_dir)?;
     log::set_boxed_logger(Box::new(file_logger))
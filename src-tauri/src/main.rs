@@ -2,16 +2,29 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 mod api;
 mod config;
+mod db;
 mod devices;
+mod error;
 mod hid;
 mod logging;
-use api::devices::{register_device, send_heartbeat};
+use api::client::ApiClient;
+use api::devices::register_device;
+use api::heartbeat::{
+    beat, get_connectivity_status, start_heartbeat_scheduler, HeartbeatMonitor,
+};
+use api::outbox::{get_outbox_status, record_entry, start_outbox_worker, Enqueued, Outbox};
+use api::remote::schedule_restart;
 use config::config_manager::{get_full_config, ConfigManager};
-use devices::barcode::{listen_to_barcode, open_symbol_scanner};
-use devices::magtek::{listen_to_magtek, open_magtek_reader};
+use devices::dispatcher::Dispatcher;
+use devices::supervisor::start_device_supervisor;
 use tauri::Manager;
 
-use api::entries::{submit_entry, CardData};
+use api::entries::{CardData, Entry};
+use db::Db;
+use devices::magnesafe::EncryptedSwipe;
+use devices::simulated::{start_simulated_input, SimulatedInput};
+use devices::visitor_pass::verify_scanned_pass;
+use error::GuestbookError;
 
 #[tauri::command]
 fn get_hid_devices() -> Vec<String> {
@@ -29,83 +42,102 @@ fn get_hid_devices() -> Vec<String> {
         .collect()
 }
 #[tauri::command]
-fn start_barcode_listener(window: tauri::Window) -> Result<(), String> {
-    log::info!("Attempting to start barcode scanner listener");
-    let api = hidapi::HidApi::new().map_err(|e| {
-        log::error!("Failed to initialize HID API: {}", e);
-        e.to_string()
-    })?;
-
-    match open_symbol_scanner(&api) {
-        Some(device) => {
-            log::info!("Barcode scanner found, starting listener");
-            listen_to_barcode(device, window);
-            Ok(())
-        }
-        None => {
-            log::warn!("No compatible barcode scanner found");
-            Err("No compatible barcode scanner found.".into())
-        }
-    }
+fn start_barcode_listener(app: tauri::AppHandle) -> Result<(), GuestbookError> {
+    devices::start_barcode_reader(app)
 }
 
 #[tauri::command]
-fn start_magtek_listener(window: tauri::Window) -> Result<(), String> {
-    log::info!("Attempting to start MagTek reader listener");
-    
+fn start_magtek_listener(app: tauri::AppHandle) -> Result<(), GuestbookError> {
     // Give USB device time to be ready on Raspberry Pi
     std::thread::sleep(std::time::Duration::from_secs(2));
-    
-    let api = hidapi::HidApi::new().map_err(|e| {
-        log::error!("Failed to initialize HID API: {}", e);
-        e.to_string()
-    })?;
 
-    match open_magtek_reader(&api) {
-        Some(device) => {
-            log::info!("MagTek reader found, starting listener");
-            listen_to_magtek(device, window);
-            Ok(())
-        }
-        None => {
-            log::warn!("No compatible MagTek reader found");
-            Err("No compatible MagTek reader found.".into())
-        }
-    }
+    devices::start_magtek_reader(app)
+}
+
+#[tauri::command]
+fn start_contactless_listener(app: tauri::AppHandle) -> Result<(), GuestbookError> {
+    devices::start_contactless_reader(app)
+}
+/// Present a swipe, scan or tap at a simulated reader (`simulated_readers`).
+#[tauri::command]
+fn simulate_reader_input(input: SimulatedInput, data: String) -> Result<(), GuestbookError> {
+    devices::simulated::simulate(input, &data)
 }
+
 #[tauri::command]
 async fn submit_swipe_entry(
-    config_manager: tauri::State<'_, ConfigManager>,
+    app: tauri::AppHandle,
     name: String,
     onecard: String,
-) -> Result<(), String> {
-    submit_entry(config_manager, CardData { name, onecard })
-        .await
-        .unwrap();
-    Ok(())
+    encrypted: Option<EncryptedSwipe>,
+) -> Result<Enqueued, GuestbookError> {
+    record_entry(
+        &app,
+        Entry::Card(CardData {
+            name,
+            onecard,
+            encrypted,
+        }),
+    )
 }
 #[tauri::command]
 async fn submit_barcode_entry(
-    config_manager: tauri::State<'_, ConfigManager>,
+    app: tauri::AppHandle,
     onecard: String,
-) -> Result<(), String> {
+) -> Result<Enqueued, GuestbookError> {
     let name = "Barcode".to_string();
-    submit_entry(config_manager, CardData { name, onecard })
-        .await
-        .unwrap();
-    Ok(())
+    record_entry(
+        &app,
+        Entry::Card(CardData {
+            name,
+            onecard,
+            encrypted: None,
+        }),
+    )
+}
+
+#[tauri::command]
+async fn submit_contactless_entry(
+    app: tauri::AppHandle,
+    onecard: String,
+) -> Result<Enqueued, GuestbookError> {
+    let name = "Contactless".to_string();
+    record_entry(
+        &app,
+        Entry::Card(CardData {
+            name,
+            onecard,
+            encrypted: None,
+        }),
+    )
 }
 
 #[tauri::command]
 async fn submit_manual_entry(
-    config_manager: tauri::State<'_, ConfigManager>,
+    app: tauri::AppHandle,
     onecard: String,
-) -> Result<(), String> {
+) -> Result<Enqueued, GuestbookError> {
     let name = "Manual Entry".to_string();
-    submit_entry(config_manager, CardData { name, onecard })
-        .await
-        .unwrap();
-    Ok(())
+    record_entry(
+        &app,
+        Entry::Card(CardData {
+            name,
+            onecard,
+            encrypted: None,
+        }),
+    )
+}
+
+/// Record the pass from a `visitor-pass` event. The webview sends the scanned
+/// `token`, which is verified again here rather than trusted.
+#[tauri::command]
+async fn submit_visitor_pass_entry(
+    app: tauri::AppHandle,
+    token: String,
+) -> Result<Enqueued, GuestbookError> {
+    let config = get_full_config(app.state::<ConfigManager>());
+    let pass = verify_scanned_pass(&config, &token)?;
+    record_entry(&app, Entry::VisitorPass(pass))
 }
 
 #[tauri::command]
@@ -120,10 +152,11 @@ async fn first_run_trigger(app: tauri::AppHandle) {
 #[tauri::command]
 async fn submit_first_run_config(
     config_manager: tauri::State<'_, ConfigManager>,
+    api_client: tauri::State<'_, ApiClient>,
     device_name: String,
     device_location: String,
     app: tauri::AppHandle,
-) -> Result<(), String> {
+) -> Result<(), GuestbookError> {
     let mut config = get_full_config(config_manager.clone());
     config.device_friendly_name = Some(device_name);
     config.device_location = Some(device_location);
@@ -133,8 +166,10 @@ async fn submit_first_run_config(
         let mut lock = config_manager.config.lock().unwrap();
         *lock = config.clone();
     }
-    config_manager.save_config().map_err(|e| e.to_string())?;
-    register_device(config_manager.clone()).await?;
+    config_manager
+        .save_config()
+        .map_err(|e| GuestbookError::Config(format!("Failed to save config: {}", e)))?;
+    register_device(config_manager.clone(), &api_client).await?;
     // Hide firstRun, show main
     if let Some(first_run_window) = app.get_webview_window("firstRun") {
         first_run_window.hide().ok();
@@ -147,20 +182,9 @@ async fn submit_first_run_config(
 }
 
 #[tauri::command]
-async fn send_heartbeat_command(
-    config_manager: tauri::State<'_, ConfigManager>,
-) -> Result<(), String> {
+async fn send_heartbeat_command(app: tauri::AppHandle) -> Result<(), GuestbookError> {
     log::info!("Sending heartbeat to server");
-    match send_heartbeat(config_manager).await {
-        Ok(_) => {
-            log::info!("Heartbeat sent successfully");
-            Ok(())
-        }
-        Err(e) => {
-            log::error!("Heartbeat failed: {}", e);
-            Err(e)
-        }
-    }
+    beat(&app).await
 }
 
 #[tauri::command]
@@ -193,20 +217,8 @@ async fn test_logging() -> Result<(), String> {
 async fn restart_appliance(app: tauri::AppHandle) -> Result<(), String> {
     log::info!("Restart appliance command received");
 
-    // Log the restart attempt
-    log::info!("Initiating application restart...");
-
-    // Use spawn to avoid blocking the current thread
-    tauri::async_runtime::spawn(async move {
-        // Small delay to ensure the response is sent back to the frontend
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-
-        // Log the restart action
-        log::info!("Executing application restart...");
-
-        // Restart the application
-        app.restart();
-    });
+    // Restart is deferred so the response is sent back to the frontend
+    schedule_restart(app);
 
     Ok(())
 }
@@ -245,16 +257,50 @@ fn main() {
         log::info!("Logging system initialized successfully");
     }
 
+    let api_client = {
+        let config = config_manager.config.lock().unwrap();
+        ApiClient::new(&config).unwrap_or_else(|e| {
+            log::error!("{}, falling back to default HTTP settings", e);
+            let mut fallback = config.clone();
+            fallback.http_proxy = None;
+            ApiClient::new(&fallback).expect("Failed to build default HTTP client")
+        })
+    };
+
+    // Entries are persisted next to the config file before being submitted
+    let db_path = config_manager.config_path.with_file_name("guestbook.db");
+    let db = Db::new(&db_path).unwrap_or_else(|e| {
+        log::error!(
+            "Failed to open entry database at {:?}, falling back to in-memory: {}",
+            db_path,
+            e
+        );
+        Db::open_in_memory().expect("Failed to open in-memory entry database")
+    });
+
     #[cfg(debug_assertions)]
     {
         builder = builder.plugin(devtools);
     }
     builder
         .manage(config_manager)
+        .manage(api_client)
+        .manage(HeartbeatMonitor::default())
+        .manage(Outbox::new(db))
+        .setup(|app| {
+            app.manage(Dispatcher::start(app.handle().clone()));
+            start_outbox_worker(app.handle().clone());
+            start_heartbeat_scheduler(app.handle().clone());
+            start_device_supervisor(app.handle().clone());
+            start_simulated_input(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_hid_devices,
             start_barcode_listener,
             start_magtek_listener,
+            start_contactless_listener,
+            simulate_reader_input,
             first_run_trigger,
             get_full_config,
             submit_first_run_config,
@@ -264,7 +310,11 @@ fn main() {
             restart_appliance,
             submit_swipe_entry,
             submit_barcode_entry,
+            submit_contactless_entry,
             submit_manual_entry,
+            submit_visitor_pass_entry,
+            get_outbox_status,
+            get_connectivity_status,
         ])
         .run(tauri::generate_context!())
         .expect("error while running Tauri application");
@@ -3,13 +3,50 @@ import { listen } from '@tauri-apps/api/event';
 import { soundManager } from '../sound/soundManager';
 
 export interface ErrorContext {
-  source: 'barcode' | 'magtek' | 'keypad' | 'network' | 'system';
+  source: 'barcode' | 'magtek' | 'contactless' | 'keypad' | 'network' | 'system';
   severity: 'low' | 'medium' | 'high' | 'critical';
   message: string;
   timestamp: Date;
   userActionable: boolean;
 }
 
+// Structured error returned by Tauri commands (GuestbookError on the Rust side)
+export interface BackendError {
+  kind:
+    | 'network'
+    | 'http'
+    | 'unauthorized'
+    | 'not_registered'
+    | 'config'
+    | 'hid'
+    | 'parse'
+    | 'storage';
+  message: string;
+  status?: number;
+}
+
+export function isBackendError(value: unknown): value is BackendError {
+  return (
+    typeof value === 'object' &&
+    value !== null &&
+    typeof (value as BackendError).kind === 'string' &&
+    typeof (value as BackendError).message === 'string'
+  );
+}
+
+// Severity for each backend error kind. Network problems are low because
+// entries are queued in the outbox and retried automatically.
+const backendSeverity: Record<BackendError['kind'], ErrorContext['severity']> = {
+  network: 'low',
+  http: 'medium',
+  unauthorized: 'high',
+  not_registered: 'high',
+  config: 'high',
+  hid: 'medium',
+  parse: 'medium',
+  storage: 'critical',
+};
+
 export class ErrorHandler {
   private static instance: ErrorHandler;
   private errorCounts: Map<string, number> = new Map();
@@ -31,14 +68,18 @@ export class ErrorHandler {
   private initializeErrorListeners() {
     // Listen for HID errors from the backend
     listen('hid-error', (event) => {
-      this.handleHIDError(event.payload as string);
+      this.handleHIDError(event.payload as BackendError | string);
     });
   }
 
-  private handleHIDError(errorMessage: string) {
+  private handleHIDError(payload: BackendError | string) {
+    const errorMessage = isBackendError(payload) ? payload.message : payload;
     const context: ErrorContext = {
       source: this.determineErrorSource(errorMessage),
-      severity: this.determineErrorSeverity(errorMessage),
+      // A listener that emits hid-error has given up on its device
+      severity: isBackendError(payload)
+        ? 'high'
+        : this.determineErrorSeverity(errorMessage),
       message: errorMessage,
       timestamp: new Date(),
       userActionable: false,
@@ -50,7 +91,7 @@ export class ErrorHandler {
 
   private determineErrorSource(
     errorMessage: string
-  ): 'barcode' | 'magtek' | 'keypad' | 'network' | 'system' {
+  ): 'barcode' | 'magtek' | 'contactless' | 'keypad' | 'network' | 'system' {
     const lowerMessage = errorMessage.toLowerCase();
 
     if (lowerMessage.includes('barcode') || lowerMessage.includes('scanner')) {
@@ -60,6 +101,11 @@ export class ErrorHandler {
       lowerMessage.includes('swipe')
     ) {
       return 'magtek';
+    } else if (
+      lowerMessage.includes('contactless') ||
+      lowerMessage.includes('pc/sc')
+    ) {
+      return 'contactless';
     } else if (
       lowerMessage.includes('keypad') ||
       lowerMessage.includes('button')
@@ -147,7 +193,11 @@ export class ErrorHandler {
         // No sound for low severity errors
         break;
       case 'medium':
-        if (context.source === 'barcode' || context.source === 'magtek') {
+        if (
+          context.source === 'barcode' ||
+          context.source === 'magtek' ||
+          context.source === 'contactless'
+        ) {
           // Play a quiet error sound for device issues
           soundManager.playBeep(400, 100);
         }
@@ -210,6 +260,34 @@ export class ErrorHandler {
     this.handleError(context);
   }
 
+  // Handle a rejected Tauri command. Structured backend errors pick their
+  // severity from `kind`; anything else falls back to the given severity.
+  public handleBackendError(
+    source: ErrorContext['source'],
+    error: unknown,
+    fallbackSeverity: ErrorContext['severity'] = 'medium'
+  ) {
+    if (isBackendError(error)) {
+      const errorSource =
+        error.kind === 'network' ||
+        error.kind === 'http' ||
+        error.kind === 'unauthorized'
+          ? 'network'
+          : error.kind === 'hid' || error.kind === 'parse'
+            ? source
+            : 'system';
+      this.handleApplicationError(
+        errorSource,
+        error.message,
+        backendSeverity[error.kind]
+      );
+      return;
+    }
+    const message =
+      error instanceof Error ? error.message : String(error ?? 'Unknown error');
+    this.handleApplicationError(source, message, fallbackSeverity);
+  }
+
   // Method to reset error counts (useful for recovery)
   public resetErrorCounts() {
     this.errorCounts.clear();
@@ -2,9 +2,44 @@ import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { errorHandler } from "../error/errorHandler";
 import { soundManager } from "../sound/soundManager";
-import { updateScanData } from "./barcodeScanner";
+import {
+	type VisitorPass,
+	updateScanData,
+	updateVisitorPassData,
+} from "./barcodeScanner";
 import { type swipeData, updateSwipeData } from "./magstripReader";
 
+interface ReaderDevice {
+	reader: string;
+	vendor_id: number;
+	product_id: number;
+	product: string | null;
+}
+
+interface BarcodeScan {
+	value: string;
+	symbology: string;
+}
+
+interface ContactlessCard {
+	onecard: string;
+	uid: string;
+}
+
+interface DuplicateEntry {
+	id: string;
+	last_entry_time: string;
+	window_secs: number;
+}
+
+// Set by the backend only when entry_mode is check_in_out
+type Direction = "in" | "out" | null;
+
+// What the submit commands did with an entry
+export type EntryResult =
+	| { status: "queued"; direction: Direction }
+	| { status: "duplicate"; last_entry_time: string };
+
 const entryDataEl = document.querySelector("#entry-data");
 export const defaultMessage =
 	"Swipe your card or scan your barcode to record an entry...";
@@ -23,13 +58,38 @@ const resetEntryData = () => {
 	}, 3000);
 };
 
+const showDirection = (direction: Direction) => {
+	if (direction && entryDataEl) {
+		entryDataEl.insertAdjacentHTML(
+			"beforeend",
+			`<p>${direction === "in" ? "Checked in" : "Checked out"}</p>`,
+		);
+	}
+};
+
+// Success feedback waits for the backend to queue the entry; duplicates get
+// "Already recorded" from the duplicate-entry listener instead
+const submitEntry = (
+	command: string,
+	args: Record<string, unknown>,
+	source: "barcode" | "magtek" | "contactless",
+	showEntry: () => void,
+) =>
+	invoke<EntryResult>(command, args)
+		.then((result) => {
+			if (result.status === "queued") {
+				showEntry();
+				soundManager.playSuccess();
+				showDirection(result.direction);
+			}
+		})
+		.catch((error) => errorHandler.handleBackendError(source, error, "high"));
+
 export async function startHIDManager() {
 	try {
 		await invoke("start_barcode_listener");
 	} catch (error) {
-		const errorMsg =
-			error instanceof Error ? error.message : "Barcode Scanner not found";
-		errorHandler.handleApplicationError("barcode", errorMsg, "medium");
+		errorHandler.handleBackendError("barcode", error, "medium");
 		setTimeout(() => {
 			resetEntryData();
 		}, 10000);
@@ -37,28 +97,42 @@ export async function startHIDManager() {
 	try {
 		await invoke("start_magtek_listener");
 	} catch (error) {
-		const errorMsg =
-			error instanceof Error ? error.message : "MagTek Reader not found";
-		errorHandler.handleApplicationError("magtek", errorMsg, "medium");
+		errorHandler.handleBackendError("magtek", error, "medium");
 		setTimeout(() => {
 			resetEntryData();
 		}, 10000);
 	}
+	// Contactless readers are optional; the backend starts one when it is plugged in
+	try {
+		await invoke("start_contactless_listener");
+	} catch (error) {
+		console.warn("No contactless reader started:", error);
+	}
+
+	// The backend reopens readers on its own when they are plugged back in
+	listen("device-disconnected", (event) => {
+		const { reader, product } = event.payload as ReaderDevice;
+		errorHandler.handleApplicationError(
+			reader === "magtek" || reader === "contactless" ? reader : "barcode",
+			`${product ?? reader} disconnected`,
+			"medium",
+		);
+	});
+	listen("device-connected", (event) => {
+		const { reader, product } = event.payload as ReaderDevice;
+		console.log(`${product ?? reader} connected`);
+	});
 
 	listen("barcode-data", (event) => {
 		try {
-			console.log("Barcode scanned:", event.payload);
-			// For barcode, expect event.payload to be the 7-digit onecard number (string or number)
+			const scan = event.payload as BarcodeScan;
+			console.log(`Barcode scanned (${scan.symbology}):`, scan.value);
+			// For barcode, expect the value to be the 7-digit onecard number
 			let onecard = "";
-			if (typeof event.payload === "string" && /^\d{7}$/.test(event.payload)) {
-				onecard = event.payload;
-			} else if (
-				typeof event.payload === "number" &&
-				event.payload.toString().length === 7
-			) {
-				onecard = event.payload.toString();
+			if (/^\d{7}$/.test(scan.value)) {
+				onecard = scan.value;
 			} else {
-				console.error("Invalid barcode payload:", event.payload);
+				console.error("Invalid barcode payload:", scan);
 				errorHandler.handleApplicationError(
 					"barcode",
 					"Invalid barcode data",
@@ -67,11 +141,10 @@ export async function startHIDManager() {
 				resetEntryData();
 				return;
 			}
-			updateScanData(onecard); // Show scanned value to user
-			// Play success sound for valid barcode
-			soundManager.playSuccess();
 			// Submit only the onecard value to the backend
-			invoke("submit_barcode_entry", { onecard });
+			submitEntry("submit_barcode_entry", { onecard }, "barcode", () =>
+				updateScanData(onecard),
+			);
 		} catch (error) {
 			console.error("Submit error:", error);
 			const errorMsg =
@@ -81,18 +154,64 @@ export async function startHIDManager() {
 		resetEntryData();
 	});
 
+	// Signature and validity window are already checked by the backend
+	listen("visitor-pass", (event) => {
+		const pass = event.payload as VisitorPass;
+		console.log("Visitor pass scanned:", pass);
+		submitEntry(
+			"submit_visitor_pass_entry",
+			{ token: pass.token },
+			"barcode",
+			() => updateVisitorPassData(pass),
+		);
+		resetEntryData();
+	});
+	listen("visitor-pass-rejected", (event) => {
+		errorHandler.handleApplicationError(
+			"barcode",
+			`Visitor pass rejected: ${event.payload as string}`,
+			"medium",
+		);
+		resetEntryData();
+	});
+
+	// The backend ignores a card presented again within the duplicate window
+	listen("duplicate-entry", (event) => {
+		const { id, last_entry_time } = event.payload as DuplicateEntry;
+		console.log(`Duplicate entry for ${id} ignored (last at ${last_entry_time})`);
+		if (entryDataEl) {
+			entryDataEl.innerHTML = `<div class="entry-data-container"><p>Already recorded</p></div>`;
+		}
+		resetEntryData();
+	});
+
+	listen("contactless-data", (event) => {
+		const card = event.payload as ContactlessCard;
+		console.log(`Contactless card tapped (UID ${card.uid}):`, card.onecard);
+		submitEntry(
+			"submit_contactless_entry",
+			{ onecard: card.onecard },
+			"contactless",
+			() => updateScanData(card.onecard),
+		);
+		resetEntryData();
+	});
+
 	listen("magtek-data", (event) => {
 		try {
 			console.log("MagTek swipe:", event.payload);
 			const swipeData = event.payload as swipeData;
-			updateSwipeData(swipeData); // Show swipe data to user
-			// Play success sound for valid swipe
-			soundManager.playSuccess();
 			// Submit the swipe data to the backend
-			invoke("submit_swipe_entry", {
-				name: swipeData.name,
-				onecard: swipeData.onecard,
-			});
+			submitEntry(
+				"submit_swipe_entry",
+				{
+					name: swipeData.name,
+					onecard: swipeData.onecard,
+					encrypted: swipeData.encrypted ?? null,
+				},
+				"magtek",
+				() => updateSwipeData(swipeData),
+			);
 		} catch (error) {
 			console.error("Submit error:", error);
 			const errorMsg =
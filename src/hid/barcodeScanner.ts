@@ -11,3 +11,22 @@ export const updateScanData = (payload: string) => {
     soundManager.playBeep(800, 150);
   }
 };
+
+
+export interface VisitorPass {
+  pass_id: string;
+  name: string;
+  valid_from: string;
+  valid_until: string;
+  /** The scanned barcode, verified again when the entry is submitted. */
+  token: string;
+}
+
+export const updateVisitorPassData = (pass: VisitorPass) => {
+  if (entryDataEl) {
+    const body = document.body;
+    body.style.backgroundColor = "green";
+    entryDataEl.innerHTML = `<div class="entry-data-container"><p>Visitor: ${pass.name}</p><p>Pass: ${pass.pass_id}</p></div>`;
+    soundManager.playBeep(800, 150);
+  }
+};
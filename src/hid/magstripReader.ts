@@ -5,6 +5,8 @@ const entryDataEl = document.getElementById('entry-data');
 export interface swipeData {
   name: string;
   onecard: string;
+  /** Encrypted swipe data from a MagneSafe reader, forwarded as-is. */
+  encrypted?: Record<string, unknown>;
 }
 
 export const updateSwipeData = (payload: swipeData) => {
@@ -1,6 +1,8 @@
 import { invoke } from '@tauri-apps/api/core';
 import { errorHandler } from './error/errorHandler';
-import { startHIDManager } from './hid/HIDManager';
+import { type EntryResult, startHIDManager } from './hid/HIDManager';
+import { startConnectivityStatus } from './network/heartbeat';
+import { startQueueStatus } from './network/queueStatus';
 import { soundManager } from './sound/soundManager';
 
 interface config {
@@ -235,18 +237,18 @@ function initializeManualEntry() {
   submitManualBtn?.addEventListener('click', async () => {
     if (currentOneCardInput.trim()) {
       try {
-        await invoke('submit_manual_entry', { onecard: currentOneCardInput });
-        // Show success message
-        soundManager.playSuccess();
-        showEntrySuccess();
+        const result = await invoke<EntryResult>('submit_manual_entry', {
+          onecard: currentOneCardInput,
+        });
+        // A duplicate is reported by the duplicate-entry listener instead
+        if (result.status === 'queued') {
+          soundManager.playSuccess();
+          showEntrySuccess();
+        }
         closeManualEntry();
       } catch (error) {
         console.error('Manual entry submission failed:', error);
-        const errorMsg =
-          error instanceof Error
-            ? error.message
-            : 'Manual entry submission failed';
-        errorHandler.handleApplicationError('keypad', errorMsg, 'high');
+        errorHandler.handleBackendError('keypad', error, 'high');
         showEntryError();
       }
     }
@@ -483,25 +485,6 @@ function showEntryError() {
   }
 }
 
-async function scheduleHeartbeat() {
-  // Random interval between 5 and 15 minutes (in ms)
-  const min = 5 * 60 * 1000;
-  const max = 15 * 60 * 1000;
-  const interval = Math.floor(Math.random() * (max - min + 1)) + min;
-  setTimeout(async () => {
-    try {
-      await invoke('send_heartbeat_command');
-      // Optionally, log success or update UI
-    } catch (e) {
-      // Optionally, log error or notify user
-      console.error('Heartbeat failed', e);
-      const errorMsg = e instanceof Error ? e.message : 'Heartbeat failed';
-      errorHandler.handleApplicationError('network', errorMsg, 'medium');
-    }
-    scheduleHeartbeat(); // Schedule next heartbeat
-  }, interval);
-}
-
 (async () => {
   const config: config = await invoke('get_full_config');
   console.log(config);
@@ -514,7 +497,6 @@ async function scheduleHeartbeat() {
   initializeMenu();
   initializeManualEntry(); // Initialize manual entry functionality
   initializeConfig(); // Initialize config modal functionality
-
-  // Heartbeat cron task: every 10 +/- 5 minutes
-  scheduleHeartbeat();
+  startQueueStatus(); // Show pending outbox entries
+  startConnectivityStatus(); // React to heartbeat results from the backend
 })();
@@ -0,0 +1,40 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { errorHandler } from '../error/errorHandler';
+
+// Mirrors ConnectivityStatus in src-tauri/src/api/heartbeat.rs
+export interface ConnectivityStatus {
+  online: boolean;
+  missed_beats: number;
+  last_success: string | null;
+  last_error: string | null;
+}
+
+function showConnectivity(status: ConnectivityStatus) {
+  const el = document.getElementById('connectivity-status');
+  if (el) {
+    el.textContent = status.online
+      ? ''
+      : 'Offline - entries will be sent when the connection returns';
+  }
+  document.body.classList.toggle('offline', !status.online);
+}
+
+// Heartbeats are scheduled by the Rust backend; the UI only reacts to status changes
+export async function startConnectivityStatus() {
+  listen<ConnectivityStatus>('connectivity-changed', (event) => {
+    showConnectivity(event.payload);
+    if (!event.payload.online) {
+      errorHandler.handleApplicationError(
+        'network',
+        event.payload.last_error ?? 'Server unreachable',
+        'low'
+      );
+    }
+  });
+  try {
+    showConnectivity(await invoke<ConnectivityStatus>('get_connectivity_status'));
+  } catch (error) {
+    console.warn('Failed to load connectivity status:', error);
+  }
+}
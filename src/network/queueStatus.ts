@@ -0,0 +1,40 @@
+import { invoke } from '@tauri-apps/api/core';
+
+export interface OutboxStatus {
+  pending: number;
+  dead: number;
+  next_retry_at: string | null;
+  last_error: string | null;
+}
+
+const POLL_INTERVAL_MS = 15000;
+
+function formatStatus(status: OutboxStatus): string {
+  if (status.pending === 0) return '';
+  const noun = status.pending === 1 ? 'entry' : 'entries';
+  let text = `${status.pending} ${noun} pending`;
+  if (status.next_retry_at) {
+    const seconds = Math.max(
+      0,
+      Math.round((new Date(status.next_retry_at).getTime() - Date.now()) / 1000)
+    );
+    text += ` (retry in ${seconds}s)`;
+  }
+  return text;
+}
+
+export async function refreshQueueStatus() {
+  const el = document.getElementById('queue-status');
+  if (!el) return;
+  try {
+    const status: OutboxStatus = await invoke('get_outbox_status');
+    el.textContent = formatStatus(status);
+  } catch (error) {
+    console.warn('Failed to load outbox status:', error);
+  }
+}
+
+export function startQueueStatus() {
+  refreshQueueStatus();
+  setInterval(refreshQueueStatus, POLL_INTERVAL_MS);
+}
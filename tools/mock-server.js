@@ -0,0 +1,81 @@
+// Minimal stand-in for the Guestbook API, for testing the kiosk locally.
+//
+//   npm run mock-server                 # listens on http://localhost:8787/api/v1
+//   curl -X POST localhost:8787/api/v1/mock/commands -d '{"type":"rescan_hid"}'
+//
+// Point the kiosk at it by setting "server_url" in wg_config.json.
+// Queued commands are returned with every heartbeat until the kiosk acks them.
+
+import http from "node:http";
+
+const PORT = Number(process.env.PORT ?? 8787);
+const PREFIX = "/api/v1";
+
+let nextCommandId = 1;
+const pendingCommands = [];
+
+function readBody(req) {
+  return new Promise((resolve) => {
+    const chunks = [];
+    req.on("data", (chunk) => chunks.push(chunk));
+    req.on("end", () => resolve(Buffer.concat(chunks).toString("utf8")));
+  });
+}
+
+function parseJson(text) {
+  try {
+    return text ? JSON.parse(text) : {};
+  } catch {
+    return null;
+  }
+}
+
+function send(res, status, body) {
+  res.writeHead(status, { "Content-Type": "application/json" });
+  res.end(JSON.stringify(body));
+}
+
+const server = http.createServer(async (req, res) => {
+  const path = req.url.startsWith(PREFIX) ? req.url.slice(PREFIX.length) : req.url;
+  const body = await readBody(req);
+  let match;
+
+  if (req.method === "POST" && path === "/devices/register") {
+    console.log("register", parseJson(body));
+    return send(res, 200, { token: `mock-token-${Date.now()}` });
+  }
+  if (req.method === "POST" && (match = path.match(/^\/devices\/heartbeat\/([^/]+)$/))) {
+    console.log(`heartbeat from ${match[1]}`, parseJson(body));
+    return send(res, 200, { commands: pendingCommands });
+  }
+  if (req.method === "POST" && (match = path.match(/^\/devices\/commands\/([^/]+)\/ack$/))) {
+    const index = pendingCommands.findIndex((c) => String(c.id) === match[1]);
+    if (index >= 0) pendingCommands.splice(index, 1);
+    console.log(`ack ${match[1]}`, parseJson(body));
+    return send(res, 200, {});
+  }
+  if (req.method === "POST" && (match = path.match(/^\/devices\/([^/]+)\/logs$/))) {
+    console.log(`received ${body.length} bytes of logs from ${match[1]}:`);
+    console.log(body.split("\n").slice(-20).join("\n"));
+    return send(res, 200, {});
+  }
+  if (req.method === "POST" && path === "/entries/submit") {
+    console.log("entry", parseJson(body));
+    return send(res, 200, {});
+  }
+  if (req.method === "POST" && path === "/mock/commands") {
+    const command = parseJson(body);
+    if (!command || typeof command.type !== "string") {
+      return send(res, 400, { error: "expected {\"type\": ...}" });
+    }
+    const queued = { ...command, id: String(nextCommandId++) };
+    pendingCommands.push(queued);
+    console.log("queued", queued);
+    return send(res, 200, queued);
+  }
+  send(res, 404, { error: `no mock route for ${req.method} ${req.url}` });
+});
+
+server.listen(PORT, () => {
+  console.log(`Mock Guestbook API listening on http://localhost:${PORT}${PREFIX}`);
+});